@@ -1,25 +1,132 @@
 use crate::util::input::read_numeric_input;
+use crate::util::options::Options;
 
-struct Pair(i128, i128);
+// Finds combinations of `k` numbers (each number in the input used at most once) that sum up to
+// `target`. Combinations are returned in ascending order, and every combination of values is
+// only returned once; even if the input contains duplicates.
+fn find_k_sums(numbers: &[i128], k: usize, target: i128, limit: usize) -> Vec<Vec<i128>> {
+    fn search(
+        sorted: &[i128],
+        k: usize,
+        target: i128,
+        prefix: &mut Vec<i128>,
+        limit: usize,
+        result: &mut Vec<Vec<i128>>,
+    ) {
+        if result.len() >= limit || sorted.len() < k {
+            return;
+        }
 
-fn find_pair(input: &Vec<i128>, search: i128) -> Result<Pair, String> {
-    if input.len() < 2 {
-        return Err(format!(
-            "Could not find pair of numbers with sum {}",
-            search
-        ));
-    }
+        if k == 1 {
+            if sorted.binary_search(&target).is_ok() {
+                let mut combination = prefix.clone();
+                combination.push(target);
+                result.push(combination);
+            }
+            return;
+        }
 
-    let first = input[0];
-    let rest = input[1..].to_vec();
+        if k == 2 {
+            // Two pointers, moving inwards from both ends of the sorted numbers.
+            let mut low = 0;
+            let mut high = sorted.len() - 1;
+            while low < high && result.len() < limit {
+                let sum = sorted[low] + sorted[high];
+                if sum < target {
+                    low += 1;
+                } else if sum > target {
+                    high -= 1;
+                } else {
+                    let mut combination = prefix.clone();
+                    combination.push(sorted[low]);
+                    combination.push(sorted[high]);
+                    result.push(combination);
 
-    for &second in &rest {
-        if first + second == search {
-            return Ok(Pair(first, second));
+                    // Skip duplicates, so we don't report the same combination again.
+                    while low < high && sorted[low] == sorted[low + 1] {
+                        low += 1;
+                    }
+                    while low < high && sorted[high] == sorted[high - 1] {
+                        high -= 1;
+                    }
+                    low += 1;
+                    high -= 1;
+                }
+            }
+            return;
         }
+
+        for i in 0..sorted.len() {
+            if i > 0 && sorted[i] == sorted[i - 1] {
+                continue; // Already tried this value in this position
+            }
+
+            prefix.push(sorted[i]);
+            search(
+                &sorted[i + 1..],
+                k - 1,
+                target - sorted[i],
+                prefix,
+                limit,
+                result,
+            );
+            prefix.pop();
+        }
+    }
+
+    if k == 0 {
+        return vec![];
     }
 
-    return find_pair(&rest, search);
+    let mut sorted = numbers.to_vec();
+    sorted.sort_unstable();
+
+    let mut result = vec![];
+    search(&sorted, k, target, &mut vec![], limit, &mut result);
+    result
+}
+
+fn find_k_sum(numbers: &[i128], k: usize, target: i128) -> Result<Vec<i128>, String> {
+    find_k_sums(numbers, k, target, 1)
+        .pop()
+        .ok_or(format!("Could not find {} numbers with sum {}", k, target))
+}
+
+#[test]
+fn test_find_k_sum() {
+    let numbers = vec![1721, 979, 366, 299, 675, 1456];
+
+    assert_eq!(find_k_sum(&numbers, 2, 2020), Ok(vec![299, 1721]));
+    assert_eq!(find_k_sum(&numbers, 3, 2020), Ok(vec![366, 675, 979]));
+    assert_eq!(find_k_sum(&numbers, 1, 366), Ok(vec![366]));
+    assert_eq!(
+        find_k_sum(&numbers, 2, 1),
+        Err("Could not find 2 numbers with sum 1".to_owned())
+    );
+    assert!(find_k_sum(&numbers, 7, 2020).is_err());
+}
+
+#[test]
+fn test_find_k_sums_duplicates() {
+    // 1010 is only allowed twice if it is in the input twice.
+    assert_eq!(
+        find_k_sums(&[1010, 5, 3], 2, 2020, 10),
+        Vec::<Vec<i128>>::new()
+    );
+    assert_eq!(
+        find_k_sums(&[1010, 1010, 1010], 2, 2020, 10),
+        vec![vec![1010, 1010]]
+    );
+
+    assert_eq!(
+        find_k_sums(&[1, 1, 2, 2, 3, 3, -1], 3, 5, 10),
+        vec![vec![-1, 3, 3], vec![1, 1, 3], vec![1, 2, 2]]
+    );
+    assert_eq!(find_k_sums(&[1, 1, 2, 2, 3, 3, -1], 3, 5, 2).len(), 2);
+    assert_eq!(
+        find_k_sums(&[4, 1, 3, 2, 0], 4, 6, 10),
+        vec![vec![0, 1, 2, 3]]
+    );
 }
 
 pub fn puzzle1() {
@@ -33,35 +140,50 @@ pub fn puzzle1() {
 
     // Now that we have our list of numbers, we need to find two numbers (x, y) => x + y = 2020
     // The result we are after is: x * y
-    match find_pair(&numbers, 2020) {
+    match find_k_sum(&numbers, 2, 2020) {
         Err(e) => eprintln!("{}", e),
-        Ok(Pair(first, second)) => {
-            println!("Found 2020 in {} + {}", first, second);
-            println!("Solution to puzzle1: {}", first * second);
+        Ok(pair) => {
+            println!("Found 2020 in {} + {}", pair[0], pair[1]);
+            println!("Solution to puzzle1: {}", pair[0] * pair[1]);
         }
     }
 }
 
-struct Triple(i128, i128, i128);
+pub fn puzzle2() {
+    let numbers = match read_numeric_input(1) {
+        Ok(numbers) => numbers,
+        Err(e) => {
+            eprintln!("Could not read/parse file: {}", e);
+            return;
+        }
+    };
 
-fn find_triplet(input: &Vec<i128>, search: i128) -> Result<Triple, String> {
-    if input.len() < 3 {
-        return Err(format!(
-            "Could not find triplet of numbers with sum {}",
-            search
-        ));
+    match find_k_sum(&numbers, 3, 2020) {
+        Err(e) => eprintln!("{}", e),
+        Ok(triple) => {
+            println!(
+                "Found 2020 in {} + {} + {}",
+                triple[0], triple[1], triple[2]
+            );
+            println!("Solution to puzzle1: {}", triple[0] * triple[1] * triple[2]);
+        }
     }
+}
 
-    let first = input[0];
-    let rest = input[1..].to_vec();
-
-    return match find_pair(&rest, search - first) {
-        Ok(Pair(second, third)) => Ok(Triple(first, second, third)),
-        Err(_) => find_triplet(&rest, search),
+// Runs a custom search, using `--k` (default 2) and `--target` (default 2020).
+// With `--all`, every combination is listed instead of just the first one.
+pub fn search(options: &Options) {
+    let (k, target) = match options
+        .get_or("k", 2)
+        .and_then(|k| options.get_or("target", 2020).map(|t| (k, t)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
     };
-}
+    if k == 0 {
+        return eprintln!("--k should be at least 1");
+    }
 
-pub fn puzzle2() {
     let numbers = match read_numeric_input(1) {
         Ok(numbers) => numbers,
         Err(e) => {
@@ -70,11 +192,26 @@ pub fn puzzle2() {
         }
     };
 
-    match find_triplet(&numbers, 2020) {
-        Err(e) => eprintln!("{}", e),
-        Ok(Triple(first, second, third)) => {
-            println!("Found 2020 in {} + {} + {}", first, second, third);
-            println!("Solution to puzzle1: {}", first * second * third);
-        }
+    let limit = if options.has("all") { usize::MAX } else { 1 };
+    let combinations = find_k_sums(&numbers, k, target, limit);
+    if combinations.is_empty() {
+        return println!("Could not find {} numbers with sum {}", k, target);
+    }
+
+    for combination in combinations {
+        let product = match combination.iter().try_fold(1i128, |a, b| a.checked_mul(*b)) {
+            None => "overflow".to_owned(),
+            Some(v) => v.to_string(),
+        };
+        println!(
+            "Found {} in {} (product = {})",
+            target,
+            combination
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(" + "),
+            product
+        );
     }
 }
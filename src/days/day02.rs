@@ -1,4 +1,7 @@
 use crate::util::input::read_mapped_input;
+use crate::util::options::Options;
+use crate::util::parse::{Captures, FromCaptures, LinePattern};
+use lazy_static::lazy_static;
 use regex::Regex;
 use std::fmt;
 
@@ -20,96 +23,365 @@ impl fmt::Display for DbEntry {
     }
 }
 
+lazy_static! {
+    // Line format: <min>-<max> <letter>: <password>
+    static ref DB_ENTRY: LinePattern = LinePattern::new(
+        r"^(?P<min>\d+)-(?P<max>\d+)\s+(?P<char>[a-z]):\s+(?P<password>\S+)$"
+    );
+}
+
+impl FromCaptures for DbEntry {
+    fn from_captures(captures: &Captures) -> Result<Self, String> {
+        let policy = Policy {
+            min: captures.get("min")?,
+            max: captures.get("max")?,
+            letter: captures.get("char")?,
+        };
+
+        Ok(DbEntry(policy, captures.get("password")?))
+    }
+}
+
 fn parse_db_entry(line: String) -> Result<DbEntry, String> {
-    // Line format:
-    // \d+-\d+\s+[a-z]:\s+[a-z]+
-    let format =
-        Regex::new(r"^(?P<min>\d+)-(?P<max>\d+)\s+(?P<char>[a-z]):\s+(?P<password>[a-z]+)$")
-            .map_err(|e| e.to_string())?;
-
-    format
-        .captures(line.as_str())
-        .map(|mat| {
-            let min = mat
-                .name("min")
-                .map(|v| v.as_str().parse::<i32>())
-                .ok_or("No match for min".to_string())?
-                .map_err(|ipe| ipe.to_string())?;
-            let max = mat
-                .name("max")
-                .map(|v| v.as_str().parse::<i32>())
-                .ok_or("No match for max".to_string())?
-                .map_err(|ipe| (ipe.to_string()))?;
-            let letter: char = mat
-                .name("char")
-                .map(|v| v.as_str().chars().nth(0))
-                .ok_or("No match for char".to_string())?
-                .ok_or("No first char".to_string())?;
-            let password = mat
-                .name("password")
-                .map(|v| v.as_str())
-                .ok_or("No match for password".to_string())?;
-
-            Ok(DbEntry(Policy { min, max, letter }, password.to_string()))
-        })
-        .ok_or(format!("Regex match failure, '{}'", line))?
-}
-
-fn password_valid(DbEntry(policy, password): &DbEntry) -> bool {
-    let target = policy.letter;
-
-    let mut count = 0;
-    for char in password.chars() {
-        if char == target {
-            count += 1;
+    DB_ENTRY.parse(&line)
+}
+
+#[test]
+fn test_parse_db_entry() {
+    let entry = parse_db_entry("1-3 b: cdefg".to_owned()).unwrap();
+    assert_eq!(format!("{}", entry), "1-3 b: cdefg");
+    assert!(parse_db_entry("1-3 b cdefg".to_owned()).is_err());
+}
+
+// A policy decides whether the password of a database entry is acceptable; if not, it tells why.
+trait PasswordPolicy {
+    fn name(&self) -> String;
+    fn check(&self, entry: &DbEntry) -> Result<(), String>;
+}
+
+// Puzzle 1: the letter should occur `min` to `max` times.
+struct LetterCount;
+
+impl PasswordPolicy for LetterCount {
+    fn name(&self) -> String {
+        "count".to_owned()
+    }
+
+    fn check(&self, DbEntry(policy, password): &DbEntry) -> Result<(), String> {
+        let count = password.chars().filter(|c| *c == policy.letter).count() as i32;
+
+        if count < policy.min || count > policy.max {
+            return Err(format!(
+                "'{}' occurs {} times, expected {} to {}",
+                policy.letter, count, policy.min, policy.max
+            ));
         }
+        Ok(())
     }
+}
+
+// Puzzle 2: `min` and `max` are (1-based!) positions, of which exactly one must be the letter.
+struct LetterPositions;
+
+impl PasswordPolicy for LetterPositions {
+    fn name(&self) -> String {
+        "positions".to_owned()
+    }
+
+    fn check(&self, DbEntry(policy, password): &DbEntry) -> Result<(), String> {
+        let at = |position: i32| {
+            if position < 1 {
+                return None;
+            }
+            password.chars().nth((position - 1) as usize)
+        };
+
+        let (first, second) = match (at(policy.min), at(policy.max)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                return Err(format!(
+                    "positions {} and {} do not both fit a password of length {}",
+                    policy.min,
+                    policy.max,
+                    password.chars().count()
+                ))
+            }
+        };
 
-    return count >= policy.min && count <= policy.max;
+        match (first == policy.letter, second == policy.letter) {
+            (true, false) | (false, true) => Ok(()),
+            (true, true) => Err(format!(
+                "'{}' is at both positions {} and {}",
+                policy.letter, policy.min, policy.max
+            )),
+            (false, false) => Err(format!(
+                "'{}' is at neither position {} nor {}",
+                policy.letter, policy.min, policy.max
+            )),
+        }
+    }
 }
 
-pub fn puzzle1() {
-    let entries = match read_mapped_input(2, parse_db_entry) {
-        Err(e) => {
-            eprintln!("{}", e);
-            return;
+struct ForbiddenLetters(Vec<char>);
+
+impl PasswordPolicy for ForbiddenLetters {
+    fn name(&self) -> String {
+        format!("forbidden:{}", self.0.iter().collect::<String>())
+    }
+
+    fn check(&self, DbEntry(_, password): &DbEntry) -> Result<(), String> {
+        let found = password
+            .chars()
+            .filter(|c| self.0.contains(c))
+            .collect::<String>();
+
+        if !found.is_empty() {
+            return Err(format!("contains forbidden letter(s) '{}'", found));
         }
-        Ok(v) => v,
-    };
+        Ok(())
+    }
+}
 
-    let mut valid = 0;
-    for entry in entries {
-        if password_valid(&entry) {
-            valid += 1;
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn parse(name: &str) -> Result<CharClass, String> {
+        match name {
+            "lower" => Ok(CharClass::Lower),
+            "upper" => Ok(CharClass::Upper),
+            "digit" => Ok(CharClass::Digit),
+            "symbol" => Ok(CharClass::Symbol),
+            _ => Err(format!(
+                "Unknown character class '{}', expected lower, upper, digit or symbol",
+                name
+            )),
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Lower => c.is_ascii_lowercase(),
+            CharClass::Upper => c.is_ascii_uppercase(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+// Every class should be represented by at least one character in the password.
+struct RequiredClasses(Vec<CharClass>);
+
+impl PasswordPolicy for RequiredClasses {
+    fn name(&self) -> String {
+        let classes = self
+            .0
+            .iter()
+            .map(|c| format!("{:?}", c).to_lowercase())
+            .collect::<Vec<_>>();
+        format!("classes:{}", classes.join("+"))
+    }
+
+    fn check(&self, DbEntry(_, password): &DbEntry) -> Result<(), String> {
+        let missing = self
+            .0
+            .iter()
+            .filter(|class| !password.chars().any(|c| class.contains(c)))
+            .map(|class| format!("{:?}", class).to_lowercase())
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(format!("has no {} characters", missing.join(" or ")));
+        }
+        Ok(())
+    }
+}
+
+struct MatchesPattern(Regex);
+
+impl PasswordPolicy for MatchesPattern {
+    fn name(&self) -> String {
+        format!("regex:{}", self.0.as_str())
+    }
+
+    fn check(&self, DbEntry(_, password): &DbEntry) -> Result<(), String> {
+        if !self.0.is_match(password) {
+            return Err(format!("does not match /{}/", self.0.as_str()));
         }
+        Ok(())
     }
+}
 
-    println!("P1: Found {} valid password(s)", valid);
+// Parses a policy description, one of:
+// count, positions, forbidden:<letters>, classes:<class>[+<class>…] or regex:<pattern>
+fn parse_policy(description: &str) -> Result<Box<dyn PasswordPolicy>, String> {
+    let (kind, argument) = match description.find(':') {
+        Some(i) => (&description[..i], Some(&description[i + 1..])),
+        None => (description, None),
+    };
+
+    match (kind, argument) {
+        ("count", None) => Ok(Box::new(LetterCount)),
+        ("positions", None) => Ok(Box::new(LetterPositions)),
+        ("forbidden", Some(letters)) => Ok(Box::new(ForbiddenLetters(letters.chars().collect()))),
+        ("classes", Some(classes)) => Ok(Box::new(RequiredClasses(
+            classes
+                .split('+')
+                .map(CharClass::parse)
+                .collect::<Result<Vec<_>, _>>()?,
+        ))),
+        ("regex", Some(pattern)) => Ok(Box::new(MatchesPattern(
+            Regex::new(pattern).map_err(|e| format!("Invalid regex for policy: {}", e))?,
+        ))),
+        _ => Err(format!("Unknown password policy '{}'", description)),
+    }
 }
 
-fn password_valid2(DbEntry(policy, password): &DbEntry) -> bool {
-    // New policy.. `min` and `max` are (1-based!) indexes. Exactly one of them must be `letter`
-    let char1 = password.chars().nth((policy.min - 1) as usize).unwrap();
-    let char2 = password.chars().nth((policy.max - 1) as usize).unwrap();
+// Returns, for every policy the entry does not satisfy, the policy name and the reason.
+fn check_entry(entry: &DbEntry, policies: &[Box<dyn PasswordPolicy>]) -> Vec<(String, String)> {
+    policies
+        .iter()
+        .filter_map(|p| p.check(entry).err().map(|e| (p.name(), e)))
+        .collect()
+}
+
+#[test]
+fn test_policies() {
+    let entry = |line: &str| parse_db_entry(line.to_owned()).unwrap();
+
+    assert_eq!(LetterCount.check(&entry("1-3 a: abcde")), Ok(()));
+    assert_eq!(LetterCount.check(&entry("2-9 c: ccccccccc")), Ok(()));
+    assert_eq!(
+        LetterCount.check(&entry("1-3 b: cdefg")),
+        Err("'b' occurs 0 times, expected 1 to 3".to_owned())
+    );
 
-    return char1 != char2 && (char1 == policy.letter || char2 == policy.letter);
+    assert_eq!(LetterPositions.check(&entry("1-3 a: abcde")), Ok(()));
+    assert_eq!(
+        LetterPositions.check(&entry("2-9 c: ccccccccc")),
+        Err("'c' is at both positions 2 and 9".to_owned())
+    );
+    // Used to panic, instead of reporting the position does not exist.
+    assert_eq!(
+        LetterPositions.check(&entry("1-30 a: abcde")),
+        Err("positions 1 and 30 do not both fit a password of length 5".to_owned())
+    );
+    assert!(LetterPositions.check(&entry("0-1 a: abcde")).is_err());
+
+    let policies = ["forbidden:xyz", "classes:lower+digit", "regex:^a", "count"]
+        .iter()
+        .map(|d| parse_policy(d))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        check_entry(&entry("1-3 x: xyzzy"), &policies),
+        vec![
+            (
+                "forbidden:xyz".to_owned(),
+                "contains forbidden letter(s) 'xyzzy'".to_owned()
+            ),
+            (
+                "classes:lower+digit".to_owned(),
+                "has no digit characters".to_owned()
+            ),
+            ("regex:^a".to_owned(), "does not match /^a/".to_owned()),
+        ]
+    );
+    // Passwords aren't only lowercase, or the other classes could never be satisfied.
+    let classes = parse_policy("classes:lower+upper+digit+symbol").unwrap();
+    assert_eq!(classes.check(&entry("1-3 a: aB3$")), Ok(()));
+    assert_eq!(
+        classes.check(&entry("1-3 a: abc")),
+        Err("has no upper or digit or symbol characters".to_owned())
+    );
+    assert!(parse_policy("classes:lower+emoji").is_err());
+    assert!(parse_policy("length").is_err());
+}
+
+fn count_valid(policy: &dyn PasswordPolicy) -> Result<usize, String> {
+    let entries = read_mapped_input(2, parse_db_entry)?;
+    Ok(entries.iter().filter(|e| policy.check(e).is_ok()).count())
+}
+
+pub fn puzzle1() {
+    match count_valid(&LetterCount) {
+        Err(e) => eprintln!("{}", e),
+        Ok(valid) => println!("P1: Found {} valid password(s)", valid),
+    }
 }
 
 pub fn puzzle2() {
+    match count_valid(&LetterPositions) {
+        Err(e) => eprintln!("{}", e),
+        Ok(valid) => println!("P2: Found {} valid password(s)", valid),
+    }
+}
+
+// Parses the policies to check, one per `--policies` option. No separator is safe to split a
+// list on, since regex policies can contain anything.
+fn parse_policies(options: &Options) -> Result<Vec<Box<dyn PasswordPolicy>>, String> {
+    options
+        .get_all::<String>("policies")?
+        .iter()
+        .map(|p| parse_policy(p))
+        .collect()
+}
+
+#[test]
+fn test_parse_policies() {
+    let parse = |args: &[&str]| {
+        Options::parse(args.iter().map(|a| a.to_string()))
+            .and_then(|o| parse_policies(&o))
+            .map(|p| p.iter().map(|p| p.name()).collect::<Vec<_>>())
+    };
+
+    assert_eq!(
+        parse(&["--policies", "regex:^a{1,3}$", "--policies", "count"]),
+        Ok(vec!["regex:^a{1,3}$".to_owned(), "count".to_owned()])
+    );
+    assert!(parse(&["--policies", "count,positions"]).is_err());
+
+    let policy = parse_policy("regex:^a{1,3}$").unwrap();
+    let entry = |line: &str| parse_db_entry(line.to_owned()).unwrap();
+    assert_eq!(policy.check(&entry("1-3 a: aa")), Ok(()));
+    assert!(policy.check(&entry("1-3 a: aaaa")).is_err());
+}
+
+// Checks all entries against every policy given with `--policies` (which can be repeated), and
+// reports every entry that fails any of them.
+pub fn report(options: &Options) {
+    let policies = match parse_policies(options) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
     let entries = match read_mapped_input(2, parse_db_entry) {
-        Err(e) => {
-            eprintln!("{}", e);
-            return;
-        }
+        Err(e) => return eprintln!("{}", e),
         Ok(v) => v,
     };
 
     let mut valid = 0;
-    for entry in entries {
-        if password_valid2(&entry) {
+    for entry in &entries {
+        let failures = check_entry(entry, &policies);
+        if failures.is_empty() {
             valid += 1;
+            continue;
+        }
+
+        println!("{}", entry);
+        for (policy, reason) in failures {
+            println!("  {}: {}", policy, reason);
         }
     }
 
-    println!("P2: Found {} valid password(s)", valid);
+    println!(
+        "{} of {} password(s) satisfy all policies",
+        valid,
+        entries.len()
+    );
 }
@@ -1,6 +1,7 @@
 use crate::util::input::read_mapped_input;
+use crate::util::options::Options;
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 enum Cell {
     Empty,
     Tree,
@@ -18,70 +19,341 @@ fn parse_line(line: String) -> Result<Vec<Cell>, String> {
     line.chars().map(parse_char).collect()
 }
 
-fn get_tree_count(map: &Vec<Vec<Cell>>, dx: usize, dy: usize) -> usize {
+// The map repeats itself to the left and right, so all rows need to be the same width.
+struct Map {
+    width: usize,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Map {
+    fn new(rows: Vec<Vec<Cell>>) -> Result<Map, String> {
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        if width == 0 {
+            return Err("The map should have at least one column and row".to_owned());
+        }
+
+        if let Some(y) = rows.iter().position(|r| r.len() != width) {
+            return Err(format!(
+                "Row {} has {} cells, but the first row has {}",
+                y + 1,
+                rows[y].len(),
+                width
+            ));
+        }
+
+        Ok(Map { width, rows })
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Slope {
+    dx: i64,
+    dy: usize,
+}
+
+impl Slope {
+    // We only ever go down the hill, otherwise we'd never reach the bottom.
+    fn new(dx: i64, dy: i64) -> Result<Slope, String> {
+        if dy <= 0 {
+            return Err(format!(
+                "Slope ({}, {}) needs to move down (dy > 0) to ever reach the bottom",
+                dx, dy
+            ));
+        }
+
+        Ok(Slope {
+            dx,
+            dy: dy as usize,
+        })
+    }
+
+    // Parses "<dx>,<dy>"
+    fn parse(data: &str) -> Result<Slope, String> {
+        let parts = data
+            .split(',')
+            .map(|p| {
+                p.trim()
+                    .parse::<i64>()
+                    .map_err(|e| format!("{} ({})", e, data))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if parts.len() != 2 {
+            return Err(format!("Expected a slope as <dx>,<dy>, but got '{}'", data));
+        }
+
+        Slope::new(parts[0], parts[1])
+    }
+}
+
+struct Path {
+    // (x, y) of every cell we land on, starting at the top-left
+    visited: Vec<(usize, usize)>,
+    trees: usize,
+}
+
+fn trace(map: &Map, slope: Slope) -> Path {
+    let mut visited = vec![];
+    let mut trees = 0;
     let mut x = 0;
     let mut y = 0;
-    let mut trees = 0;
 
-    while y < map.len() {
-        if map[y][x] == Cell::Tree {
+    while y < map.rows.len() {
+        visited.push((x, y));
+        if map.rows[y][x] == Cell::Tree {
             trees += 1;
         }
 
-        x += dx;
-        x %= map[y].len(); // wrap around
-        y += dy;
+        // wrap around, in either direction
+        x = (x as i64 + slope.dx).rem_euclid(map.width as i64) as usize;
+        y += slope.dy;
     }
 
-    trees
+    Path { visited, trees }
+}
+
+fn get_tree_count(map: &Map, slope: Slope) -> usize {
+    trace(map, slope).trees
+}
+
+// Tries all slopes with -max_dx <= dx <= max_dx and 1 <= dy <= max_dy; and returns the one that
+// hits the least trees. On a tie, the slope found first wins.
+fn find_best_slope(map: &Map, max_dx: i64, max_dy: i64) -> Result<(Slope, usize), String> {
+    let mut best: Option<(Slope, usize)> = None;
+
+    for dy in 1..=max_dy {
+        for dx in -max_dx..=max_dx {
+            let slope = Slope::new(dx, dy)?;
+            let trees = get_tree_count(map, slope);
+            if best.map(|(_, t)| trees < t).unwrap_or(true) {
+                best = Some((slope, trees));
+            }
+        }
+    }
+
+    best.ok_or(format!(
+        "No slopes to try with max dx {} and max dy {}",
+        max_dx, max_dy
+    ))
+}
+
+// Draws the map with the path on top of it: 'O' where we landed on open snow, 'X' on a tree.
+fn render(map: &Map, path: &Path) -> String {
+    let mut lines = map
+        .rows
+        .iter()
+        .map(|r| {
+            r.iter()
+                .map(|c| match c {
+                    Cell::Tree => '#',
+                    Cell::Empty => '.',
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    for &(x, y) in &path.visited {
+        lines[y][x] = match map.rows[y][x] {
+            Cell::Tree => 'X',
+            Cell::Empty => 'O',
+        };
+    }
+
+    lines
+        .iter()
+        .map(|l| l.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+fn example_map() -> Map {
+    let lines = [
+        "..##.......",
+        "#...#...#..",
+        ".#....#..#.",
+        "..#.#...#.#",
+        ".#...##..#.",
+        "..#.##.....",
+        ".#.#.#....#",
+        ".#........#",
+        "#.##...#...",
+        "#...##....#",
+        ".#..#...#.#",
+    ];
+    Map::new(
+        lines
+            .iter()
+            .map(|l| parse_line(l.to_string()).unwrap())
+            .collect(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_tree_counts() {
+    let map = example_map();
+    let count = |dx, dy| get_tree_count(&map, Slope::new(dx, dy).unwrap());
+
+    assert_eq!(count(1, 1), 2);
+    assert_eq!(count(3, 1), 7);
+    assert_eq!(count(5, 1), 3);
+    assert_eq!(count(7, 1), 4);
+    assert_eq!(count(1, 2), 2);
+    // Going left wraps around the other way; -8 is the same as 3 on a map 11 wide.
+    assert_eq!(count(-8, 1), 7);
+
+    assert!(Slope::new(1, 0).is_err());
+    assert!(Slope::parse("3,-1").is_err());
+    assert_eq!(Slope::parse("-3, 2"), Ok(Slope { dx: -3, dy: 2 }));
+    assert!(Map::new(vec![vec![Cell::Empty; 2], vec![Cell::Tree; 3]]).is_err());
+}
+
+#[test]
+fn test_best_slope_and_render() {
+    let map = example_map();
+
+    let (slope, trees) = find_best_slope(&map, 3, 2).unwrap();
+    assert_eq!(trees, get_tree_count(&map, slope));
+    assert_eq!(trees, 1);
+
+    let path = trace(&map, Slope::new(3, 1).unwrap());
+    assert_eq!(path.visited.len(), 11);
+    assert_eq!(path.visited[4], (1, 4));
+    let drawn = render(&map, &path);
+    assert_eq!(drawn.lines().next(), Some("O.##......."));
+    assert_eq!(drawn.lines().nth(2), Some(".#....X..#."));
+}
+
+fn read_map() -> Result<Map, String> {
+    read_mapped_input(3, parse_line).and_then(Map::new)
 }
 
 pub fn puzzle1() {
-    let cells = match read_mapped_input(3, parse_line) {
+    let map = match read_map() {
         Err(error) => {
             println!("{}", error);
             return;
         }
-        Ok(cells) => cells,
+        Ok(map) => map,
     };
 
     // We need to start top-left (0,0)
     // Every time we make a move of 3 right, 1 down
     // Count the number of tree-cells we find
-    println!("Puzzle 1: found {} trees", get_tree_count(&cells, 3, 1));
+    println!(
+        "Puzzle 1: found {} trees",
+        get_tree_count(&map, Slope { dx: 3, dy: 1 })
+    );
 }
 
 pub fn puzzle2() {
-    let cells = match read_mapped_input(3, parse_line) {
+    let map = match read_map() {
         Err(error) => {
             println!("{}", error);
             return;
         }
-        Ok(cells) => cells,
+        Ok(map) => map,
     };
 
-    // We need to find the trees encountered on the following slopes:
-    // dx|dy
-    //  1| 1
-    //  3| 1
-    //  5| 1
-    //  7| 1
-    //  1| 2
-    // And multiply the results for the answer.
-    let tree11 = get_tree_count(&cells, 1, 1);
-    let tree31 = get_tree_count(&cells, 3, 1);
-    let tree51 = get_tree_count(&cells, 5, 1);
-    let tree71 = get_tree_count(&cells, 7, 1);
-    let tree12 = get_tree_count(&cells, 1, 2);
+    // We need to find the trees encountered on the following slopes, and multiply the results
+    // for the answer.
+    let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
 
     println!("Puzzle 2:");
-    println!("Right 1, down 1 = {}", tree11);
-    println!("Right 3, down 1 = {}", tree31);
-    println!("Right 5, down 1 = {}", tree51);
-    println!("Right 7, down 1 = {}", tree71);
-    println!("Right 1, down 2 = {}", tree12);
-    println!(
-        "Puzzle answer = {}",
-        tree11 * tree31 * tree51 * tree71 * tree12
+    let mut answer = 1;
+    for &(dx, dy) in &slopes {
+        let trees = get_tree_count(&map, Slope { dx, dy });
+        println!("Right {}, down {} = {}", dx, dy, trees);
+        answer *= trees;
+    }
+    println!("Puzzle answer = {}", answer);
+}
+
+// Checks that no option is given that `analyse` would ignore.
+fn check_options(options: &Options) -> Result<(), String> {
+    let best = options.has("best");
+    let conflict = ["slopes", "render"]
+        .iter()
+        .find(|o| best && options.has(o))
+        .map(|o| format!("--{} can't be combined with --best", o));
+    let unused = ["max-dx", "max-dy"]
+        .iter()
+        .find(|o| !best && options.has(o))
+        .map(|o| format!("--{} can only be used with --best", o));
+
+    match conflict.or(unused) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_check_options() {
+    let check = |args: &[&str]| {
+        Options::parse(args.iter().map(|a| a.to_string())).and_then(|o| check_options(&o))
+    };
+
+    assert_eq!(check(&["--best", "--max-dx", "3"]), Ok(()));
+    assert_eq!(check(&["--slopes", "3,1", "--render"]), Ok(()));
+    assert_eq!(check(&["--render"]), Ok(()));
+    assert_eq!(
+        check(&["--best", "--render"]),
+        Err("--render can't be combined with --best".to_owned())
+    );
+    assert!(check(&["--best", "--slopes", "3,1"]).is_err());
+    assert_eq!(
+        check(&["--slopes", "3,1", "--max-dy", "2"]),
+        Err("--max-dy can only be used with --best".to_owned())
     );
 }
+
+// Either counts the trees for every slope in `--slopes` ("<dx>,<dy>;…", the puzzle 2 slopes if
+// not given), drawing each path with `--render`; or with `--best` finds the slope hitting the
+// fewest trees within `--max-dx` (7) and `--max-dy` (2).
+pub fn analyse(options: &Options) {
+    if let Err(e) = check_options(options) {
+        return eprintln!("{}", e);
+    }
+
+    let map = match read_map() {
+        Err(error) => return eprintln!("{}", error),
+        Ok(map) => map,
+    };
+
+    if options.has("best") {
+        match options
+            .get_or("max-dx", 7)
+            .and_then(|dx| options.get_or("max-dy", 2).map(|dy| (dx, dy)))
+            .and_then(|(dx, dy)| find_best_slope(&map, dx, dy))
+        {
+            Err(e) => eprintln!("{}", e),
+            Ok((slope, trees)) => println!(
+                "Best slope: right {}, down {}, hitting {} trees",
+                slope.dx, slope.dy, trees
+            ),
+        }
+        return;
+    }
+
+    let slopes = match options
+        .get_or("slopes", "1,1;3,1;5,1;7,1;1,2".to_owned())
+        .and_then(|s| {
+            s.split(';')
+                .map(Slope::parse)
+                .collect::<Result<Vec<_>, _>>()
+        }) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    for slope in slopes {
+        let path = trace(&map, slope);
+        println!(
+            "Right {}, down {} = {} trees",
+            slope.dx, slope.dy, path.trees
+        );
+        if options.has("render") {
+            println!("{}\n", render(&map, &path));
+        }
+    }
+}
@@ -1,15 +1,409 @@
 use crate::util::input::read_raw_input;
+use crate::util::options::Options;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{read_to_string, write};
 
-#[derive(Eq, PartialEq)]
+// Which fields a passport has, and what their values should look like, is described by a schema
+// (see input/day4_schema.txt); so the rules can be changed without touching the code.
+const SCHEMA_FILE: &str = "input/day4_schema.txt";
+
+#[derive(Eq, PartialEq, Debug)]
 struct Passport {
-    byr: Option<String>, // Birth Year
-    iyr: Option<String>, // Issue Year
-    eyr: Option<String>, // Expiration Year
-    hgt: Option<String>, // Height
-    hcl: Option<String>, // Hair colour
-    ecl: Option<String>, // Eye colour
-    pid: Option<String>, // Passport ID
-    cid: Option<String>, // Country ID
+    fields: HashMap<String, String>,
+    // Anything in the entry that isn't a key:value pair
+    malformed: Vec<String>,
+    // Earlier key:value pairs that were replaced, when a key is given more than once
+    repeated: Vec<String>,
+}
+
+impl Passport {
+    fn get(&self, name: &str) -> Option<&String> {
+        self.fields.get(name)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+enum Rule {
+    Year(i32, i32),
+    Units(Vec<(String, i32, i32)>),
+    Colour,
+    OneOf(Vec<String>),
+    Digits(usize),
+    Any,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+struct Field {
+    name: String,
+    required: bool,
+    rule: Rule,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+struct Schema {
+    fields: Vec<Field>,
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| format!("{} ({})", e, value))
+}
+
+fn parse_rule(name: &str, args: &[&str]) -> Result<Rule, String> {
+    match (name, args) {
+        ("year", [min, max]) => Ok(Rule::Year(parse_number(min)?, parse_number(max)?)),
+        ("units", units) if !units.is_empty() => units
+            .iter()
+            .map(|u| {
+                let (unit, range) = u.split_at(
+                    u.find('=')
+                        .ok_or(format!("Expected <unit>=<min>-<max>, but got '{}'", u))?,
+                );
+                let range = &range[1..];
+                let dash = range
+                    .find('-')
+                    .ok_or(format!("Expected <min>-<max>, but got '{}'", range))?;
+                Ok((
+                    unit.to_owned(),
+                    parse_number(&range[..dash])?,
+                    parse_number(&range[dash + 1..])?,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(Rule::Units),
+        ("colour", []) => Ok(Rule::Colour),
+        ("oneof", values) if !values.is_empty() => Ok(Rule::OneOf(
+            values.iter().map(|v| (*v).to_owned()).collect(),
+        )),
+        ("digits", [length]) => Ok(Rule::Digits(parse_number(length)?)),
+        ("any", []) => Ok(Rule::Any),
+        _ => Err(format!(
+            "Unknown rule '{}' or wrong number of arguments ({})",
+            name,
+            args.len()
+        )),
+    }
+}
+
+// Every line is `<name> <required|optional> <rule> [arguments]`; blank lines and lines starting
+// with '#' are ignored.
+fn parse_schema(data: &str) -> Result<Schema, String> {
+    let mut fields: Vec<Field> = vec![];
+
+    for (i, line) in data.split('\n').enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |e: String| format!("Schema line {}: {}", i + 1, e);
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        if parts.len() < 3 {
+            return Err(error(format!(
+                "Expected <name> <required|optional> <rule>, but got '{}'",
+                line
+            )));
+        }
+
+        let required = match parts[1] {
+            "required" => true,
+            "optional" => false,
+            other => {
+                return Err(error(format!(
+                    "Expected required or optional, not '{}'",
+                    other
+                )))
+            }
+        };
+        if fields.iter().any(|f| f.name == parts[0]) {
+            return Err(error(format!("Field '{}' is defined twice", parts[0])));
+        }
+
+        fields.push(Field {
+            name: parts[0].to_owned(),
+            required,
+            rule: parse_rule(parts[2], &parts[3..]).map_err(error)?,
+        });
+    }
+
+    Ok(Schema { fields })
+}
+
+fn read_schema(path: &str) -> Result<Schema, String> {
+    read_to_string(path)
+        .map_err(|e| format!("Could not read schema {}: {}", path, e))
+        .and_then(|data| parse_schema(&data))
+}
+
+fn is_digits(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+// Checks a value against a rule; returning why it does not match.
+fn check_rule(rule: &Rule, value: &str) -> Result<(), String> {
+    match rule {
+        Rule::Year(min, max) => {
+            if value.len() != 4 || !is_digits(value) {
+                return Err("should be a 4 digit year".to_owned());
+            }
+            let year = parse_number::<i32>(value)?;
+            if year < *min || year > *max {
+                return Err(format!("should be between {} and {}", min, max));
+            }
+            Ok(())
+        }
+        Rule::Units(units) => {
+            let (unit, min, max) = units
+                .iter()
+                .find(|(unit, _, _)| value.ends_with(unit.as_str()))
+                .ok_or(format!(
+                    "should end in one of {}",
+                    units
+                        .iter()
+                        .map(|(u, _, _)| u.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))?;
+            let number = &value[..value.len() - unit.len()];
+            if !is_digits(number) {
+                return Err(format!("should be a number followed by {}", unit));
+            }
+            match number.parse::<i32>() {
+                Ok(v) if v >= *min && v <= *max => Ok(()),
+                _ => Err(format!(
+                    "should be between {}{} and {}{}",
+                    min, unit, max, unit
+                )),
+            }
+        }
+        Rule::Colour => {
+            // HTML color (#1234ab)
+            match value.strip_prefix('#') {
+                Some(hex)
+                    if hex.len() == 6
+                        && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) =>
+                {
+                    Ok(())
+                }
+                _ => Err("should be a colour like #1234ab".to_owned()),
+            }
+        }
+        Rule::OneOf(values) => {
+            if values.iter().any(|v| v == value) {
+                Ok(())
+            } else {
+                Err(format!("should be one of {}", values.join(", ")))
+            }
+        }
+        Rule::Digits(length) => {
+            if value.len() == *length && is_digits(value) {
+                Ok(())
+            } else {
+                Err(format!("should be a {} digit number", length))
+            }
+        }
+        Rule::Any => Ok(()),
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Default)]
+struct Report {
+    missing: Vec<String>,
+    // field name, value and the reason it is invalid
+    invalid: Vec<(String, String, String)>,
+}
+
+impl Report {
+    fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.invalid.is_empty()
+    }
+}
+
+fn check_passport(schema: &Schema, passport: &Passport) -> Report {
+    let mut report = Report::default();
+
+    for field in &schema.fields {
+        match passport.get(&field.name) {
+            None if field.required => report.missing.push(field.name.clone()),
+            None => {}
+            Some(value) => {
+                if let Err(reason) = check_rule(&field.rule, value) {
+                    report
+                        .invalid
+                        .push((field.name.clone(), value.clone(), reason));
+                }
+            }
+        }
+    }
+
+    report
+}
+
+// Heights can be in either unit; this converts them to `unit`, so they can be compared.
+fn normalise_height(value: &str, unit: &str) -> Option<f64> {
+    const CM_PER_UNIT: [(&str, f64); 2] = [("cm", 1.0), ("in", 2.54)];
+
+    let (from, factor) = CM_PER_UNIT.iter().find(|(u, _)| value.ends_with(u))?;
+    let (_, to_factor) = CM_PER_UNIT.iter().find(|(u, _)| *u == unit)?;
+    let number = &value[..value.len() - from.len()];
+    if !is_digits(number) {
+        return None;
+    }
+
+    number.parse::<f64>().ok().map(|v| v * factor / to_factor)
+}
+
+// Finds the pids used by more than one passport, with the (0-based) passports using them.
+fn duplicate_pids(passports: &[Passport]) -> BTreeMap<String, Vec<usize>> {
+    let mut pids: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, passport) in passports.iter().enumerate() {
+        if let Some(pid) = passport.get("pid") {
+            pids.entry(pid.clone()).or_default().push(i);
+        }
+    }
+
+    pids.into_iter().filter(|(_, p)| p.len() > 1).collect()
+}
+
+// Counts the keys that are not in the schema.
+fn unknown_keys(schema: &Schema, passports: &[Passport]) -> BTreeMap<String, usize> {
+    let mut keys = BTreeMap::new();
+    for passport in passports {
+        for key in passport.fields.keys() {
+            if !schema.fields.iter().any(|f| &f.name == key) {
+                *keys.entry(key.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    keys
+}
+
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// One row per passport: its number, every schema field (empty when missing), the height in
+// `unit`, whether it is valid and whether its pid is shared with another passport.
+fn to_csv(schema: &Schema, passports: &[Passport], unit: &str) -> String {
+    let duplicates = duplicate_pids(passports);
+
+    let mut header = vec!["passport".to_owned()];
+    header.extend(schema.fields.iter().map(|f| csv_value(&f.name)));
+    header.push(format!("height_{}", unit));
+    header.push("valid".to_owned());
+    header.push("duplicate_pid".to_owned());
+
+    let mut lines = vec![header.join(",")];
+    for (i, passport) in passports.iter().enumerate() {
+        let mut row = vec![(i + 1).to_string()];
+        row.extend(schema.fields.iter().map(|f| {
+            passport
+                .get(&f.name)
+                .map(|v| csv_value(v))
+                .unwrap_or_default()
+        }));
+        row.push(
+            passport
+                .get("hgt")
+                .and_then(|h| normalise_height(h, unit))
+                .map(|h| format!("{:.1}", h))
+                .unwrap_or_default(),
+        );
+        row.push(check_passport(schema, passport).is_valid().to_string());
+        row.push(
+            passport
+                .get("pid")
+                .map(|pid| duplicates.contains_key(pid))
+                .unwrap_or(false)
+                .to_string(),
+        );
+        lines.push(row.join(","));
+    }
+
+    lines.join("\n")
+}
+
+// An array with an object per passport; unlike the CSV this keeps the unknown keys, malformed
+// pairs and replaced repeated pairs as well.
+fn to_json(schema: &Schema, passports: &[Passport], unit: &str) -> String {
+    let duplicates = duplicate_pids(passports);
+
+    let objects = passports
+        .iter()
+        .enumerate()
+        .map(|(i, passport)| {
+            // Sorted, so the output doesn't change between runs.
+            let fields = passport
+                .fields
+                .iter()
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .map(|(k, v)| format!("{}: {}", json_string(k), json_string(v)))
+                .collect::<Vec<_>>();
+            let unknown = unknown_keys(schema, std::slice::from_ref(passport))
+                .keys()
+                .map(|k| json_string(k))
+                .collect::<Vec<_>>();
+            let malformed = passport
+                .malformed
+                .iter()
+                .map(|m| json_string(m))
+                .collect::<Vec<_>>();
+            let repeated = passport
+                .repeated
+                .iter()
+                .map(|r| json_string(r))
+                .collect::<Vec<_>>();
+            let height = passport
+                .get("hgt")
+                .and_then(|h| normalise_height(h, unit))
+                .map(|h| format!("{:.1}", h))
+                .unwrap_or_else(|| "null".to_owned());
+            let duplicate = passport
+                .get("pid")
+                .map(|pid| duplicates.contains_key(pid))
+                .unwrap_or(false);
+
+            format!(
+                "  {{\"passport\": {}, \"fields\": {{{}}}, \"height_{}\": {}, \"valid\": {}, \"duplicate_pid\": {}, \"unknown_keys\": [{}], \"malformed\": [{}], \"repeated\": [{}]}}",
+                i + 1,
+                fields.join(", "),
+                unit,
+                height,
+                check_passport(schema, passport).is_valid(),
+                duplicate,
+                unknown.join(", "),
+                malformed.join(", "),
+                repeated.join(", ")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!("[\n{}\n]", objects.join(",\n"))
 }
 
 fn read_entry(data: String) -> Passport {
@@ -18,45 +412,25 @@ fn read_entry(data: String) -> Passport {
         .flat_map(|line| line.split(" "))
         .map(|line| line.trim());
 
-    let mut byr = None;
-    let mut iyr = None;
-    let mut eyr = None;
-    let mut hgt = None;
-    let mut hcl = None;
-    let mut ecl = None;
-    let mut pid = None;
-    let mut cid = None;
-
-    for pair in pairs {
-        match &pair.chars().nth(3) {
-            Some(':') => {} // Continue function if we found a colon at the right place.
-            Some(_) | None => continue,
-        }
-
-        let value = pair[4..].to_owned();
-
-        match &pair[0..3] {
-            "byr" => byr = Some(value),
-            "iyr" => iyr = Some(value),
-            "eyr" => eyr = Some(value),
-            "hgt" => hgt = Some(value),
-            "hcl" => hcl = Some(value),
-            "ecl" => ecl = Some(value),
-            "pid" => pid = Some(value),
-            "cid" => cid = Some(value),
-            _ => {} // Ignore unknown values
+    let mut fields = HashMap::new();
+    let mut malformed = vec![];
+    let mut repeated = vec![];
+    for pair in pairs.filter(|p| !p.is_empty()) {
+        match pair.find(':') {
+            Some(i) if i > 0 => {
+                let key = &pair[..i];
+                if let Some(earlier) = fields.insert(key.to_owned(), pair[i + 1..].to_owned()) {
+                    repeated.push(format!("{}:{}", key, earlier));
+                }
+            }
+            _ => malformed.push(pair.to_owned()),
         }
     }
 
     Passport {
-        byr,
-        iyr,
-        eyr,
-        hgt,
-        hcl,
-        ecl,
-        pid,
-        cid,
+        fields,
+        malformed,
+        repeated,
     }
 }
 
@@ -75,25 +449,13 @@ fn read_input_file() -> Result<Vec<Passport>, String> {
     };
 }
 
-fn is_passport_valid1(passport: &Passport) -> bool {
-    // All fields, except cid, are required in this implementation.
-    match passport {
-        Passport {
-            byr: Some(_),
-            iyr: Some(_),
-            eyr: Some(_),
-            hgt: Some(_),
-            hcl: Some(_),
-            ecl: Some(_),
-            pid: Some(_),
-            ..
-        } => true,
-        _ => false,
-    }
+fn read_input() -> Result<(Schema, Vec<Passport>), String> {
+    let schema = read_schema(SCHEMA_FILE)?;
+    read_input_file().map(|passports| (schema, passports))
 }
 
 pub fn puzzle1() {
-    let passports = match read_input_file() {
+    let (schema, passports) = match read_input() {
         Err(e) => {
             println!("{}", e);
             return;
@@ -101,122 +463,186 @@ pub fn puzzle1() {
         Ok(v) => v,
     };
 
-    let valid = passports.iter().filter(|&p| is_passport_valid1(p)).count();
+    // All required fields need to be there, but we don't care about their values yet.
+    let valid = passports
+        .iter()
+        .filter(|&p| check_passport(&schema, p).missing.is_empty())
+        .count();
     println!("Puzzle 1: We have {} valid passports", valid);
 }
 
-fn is_valid_byr(byr: &String) -> bool {
-    // BYR => 4 digits (number) and between 1920-2002 incl
-    match byr.parse::<i32>() {
-        Err(_) => false,
-        Ok(v) => v >= 1290 && v <= 2002,
-    }
+#[cfg(test)]
+fn test_schema() -> Schema {
+    parse_schema(include_str!("../../input/day4_schema.txt")).unwrap()
 }
 
-fn is_valid_iyr(iyr: &String) -> bool {
-    // IYR => 4 digits (number) and between 2010-2020 incl
-    match iyr.parse::<i32>() {
-        Err(_) => false,
-        Ok(v) => v >= 2010 && v <= 2020,
-    }
-}
+#[test]
+fn test_parse_schema() {
+    let schema = test_schema();
+    assert_eq!(schema.fields.len(), 8);
+    assert_eq!(
+        schema.fields[3],
+        Field {
+            name: "hgt".to_owned(),
+            required: true,
+            rule: Rule::Units(vec![("cm".to_owned(), 150, 193), ("in".to_owned(), 59, 76)]),
+        }
+    );
+    assert_eq!(schema.fields[7].rule, Rule::Any);
+    assert!(!schema.fields[7].required);
 
-fn is_valid_eyr(eyr: &String) -> bool {
-    // EYR => 4 digits (number) and between 2020-2030 incl
-    match eyr.parse::<i32>() {
-        Err(_) => false,
-        Ok(v) => v >= 2020 && v <= 2030,
-    }
+    assert_eq!(
+        parse_schema("byr required\n"),
+        Err(
+            "Schema line 1: Expected <name> <required|optional> <rule>, but got 'byr required'"
+                .to_owned()
+        )
+    );
+    assert!(parse_schema("byr maybe any").is_err());
+    assert!(parse_schema("byr required year 1920").is_err());
+    assert!(parse_schema("hgt required units cm150").is_err());
+    assert!(parse_schema("x required any\nx optional any").is_err());
 }
 
-fn is_valid_hgt(hgt: &String) -> bool {
-    // HGT => number followed by 'in' or 'cm'
-    //      'in' => 59-76 incl
-    //      'cm' => 150-193 incl
-    let len = hgt.len();
-    let val = &hgt[0..(len - 2)];
-    let unit = &hgt[(len - 2)..];
+#[test]
+fn test_check_passport() {
+    let schema = test_schema();
+    let check = |data: &str| check_passport(&schema, &read_entry(data.to_owned()));
 
-    match val.parse::<i32>() {
-        Err(_) => false,
-        Ok(v) => match unit {
-            "in" => v >= 59 && v <= 76,
-            "cm" => v >= 150 && v <= 193,
-            _ => false,
-        },
-    }
-}
+    assert!(
+        check("pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f").is_valid()
+    );
+    assert!(check(
+        "eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm"
+    )
+    .is_valid());
 
-fn is_valid_hcl(hcl: &String) -> bool {
-    // HCL => HTML color (#1234AB)
-    if hcl.len() != 7 || hcl.chars().nth(0).unwrap() != '#' {
-        return false;
-    }
+    // The birth year should be 1920 at the earliest.
+    let report = check("pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1919 hcl:#623a2f");
+    assert_eq!(
+        report.invalid,
+        vec![(
+            "byr".to_owned(),
+            "1919".to_owned(),
+            "should be between 1920 and 2002".to_owned()
+        )]
+    );
+
+    let report = check("eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018");
+    assert_eq!(report.missing, vec!["byr".to_owned()]);
+    assert_eq!(
+        report
+            .invalid
+            .iter()
+            .map(|(f, _, _)| f.as_str())
+            .collect::<Vec<_>>(),
+        vec!["eyr", "hgt", "pid"]
+    );
 
-    hcl[1..].chars().all(|c| match c {
-        '0'..='9' | 'a'..='f' => true,
-        _ => return false,
-    })
+    assert!(check_rule(&Rule::Colour, "#123abz").is_err());
+    assert!(check_rule(&Rule::Colour, "#123abc").is_ok());
+    assert!(check_rule(&Rule::Year(1920, 2002), "02002").is_err());
+    assert!(check_rule(&Rule::Digits(9), "0123456789").is_err());
 }
 
-fn is_valid_ecl(ecl: &String) -> bool {
-    // ECL => one of 'amb', 'blu', 'brn', 'gry', 'grn', 'hzl', 'oth'
-    if ecl.len() != 3 {
-        return false;
-    }
+#[test]
+fn test_passport_fuzz() {
+    use crate::util::fuzz::check;
 
-    match &ecl[0..3] {
-        "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth" => true,
-        _ => false,
-    }
+    let schema = test_schema();
+    check(
+        "day 4 parsing and validation does not panic",
+        |rng| {
+            let keys = [
+                "byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid", "xyz",
+            ];
+            // Mostly complete passports, so the validation rules get to see the values.
+            let mut pairs = vec![];
+            for key in keys.iter() {
+                if rng.chance(90) {
+                    pairs.push(format!("{}:{}", key, rng.noise(4)));
+                } else if rng.chance(50) {
+                    pairs.push(rng.noise(8));
+                }
+            }
+            pairs.join(" ")
+        },
+        |data| {
+            // The report checks every field, so every rule gets to see the values.
+            check_passport(&schema, &read_entry(data.to_owned()));
+            Ok(())
+        },
+    );
 }
 
-fn is_valid_pid(pid: &String) -> bool {
-    // PID => 9 digit number (may have leading zeroes)
-    pid.len() == 9
-        && pid.chars().all(|c| match c {
-            '0'..='9' => true,
-            _ => false,
-        })
+#[test]
+fn test_passport_regressions() {
+    // These used to panic on slicing through a short value or a multi-byte character.
+    let units = test_schema().fields.remove(3).rule;
+    assert!(check_rule(&units, "").is_err());
+    assert!(check_rule(&units, "c").is_err());
+    assert!(check_rule(&units, "1€").is_err());
+    assert!(check_rule(&units, "€cm").is_err());
+    assert!(check_rule(&Rule::Colour, "#€1234").is_err());
+    assert!(check_rule(&Rule::OneOf(vec!["amb".to_owned()]), "é1").is_err());
+    assert_eq!(
+        read_entry("ab€:1".to_owned()).get("ab€"),
+        Some(&"1".to_owned())
+    );
 }
 
-fn is_passport_valid2(passport: &Passport) -> bool {
-    // CID is still optional. However:
-    // BYR => 4 digits (number) and between 1920-2002 incl
-    // IYR => 4 digits (number) and between 2010-2020 incl
-    // EYR => 4 digits (number) and between 2020-2030 incl
-    // HGT => number followed by 'in' or 'cm'
-    //      'in' => 59-76 incl
-    //      'cm' => 150-193 incl
-    // HCL => HTML color (#1234AB)
-    // ECL => one of 'amb', 'blu', 'brn', 'gry', 'grn', 'hzl', 'oth'
-    // PID => 9 digit number (may have leading zeroes)
-    match passport {
-        Passport {
-            byr: Some(byr),
-            iyr: Some(iyr),
-            eyr: Some(eyr),
-            hgt: Some(hgt),
-            hcl: Some(hcl),
-            ecl: Some(ecl),
-            pid: Some(pid),
-            ..
-        } if is_valid_byr(byr)
-            && is_valid_iyr(iyr)
-            && is_valid_eyr(eyr)
-            && is_valid_hgt(hgt)
-            && is_valid_hcl(hcl)
-            && is_valid_ecl(ecl)
-            && is_valid_pid(pid) =>
-        {
-            true
-        }
-        _ => false,
-    }
+#[test]
+fn test_export() {
+    let schema = parse_schema("pid required digits 9\nhgt optional any").unwrap();
+    let passports = vec![
+        read_entry("pid:000000001 hgt:70in".to_owned()),
+        read_entry("pid:000000001 hgt:180cm note:\"a,b\" stray".to_owned()),
+        read_entry("pid:12 hgt:tall hgt:short".to_owned()),
+    ];
+
+    assert_eq!(normalise_height("70in", "cm"), Some(177.8));
+    assert_eq!(normalise_height("254cm", "in"), Some(100.0));
+    assert_eq!(normalise_height("70", "cm"), None);
+    assert_eq!(normalise_height("-1cm", "cm"), None);
+
+    assert_eq!(
+        duplicate_pids(&passports),
+        vec![("000000001".to_owned(), vec![0, 1])]
+            .into_iter()
+            .collect()
+    );
+    assert_eq!(
+        unknown_keys(&schema, &passports),
+        vec![("note".to_owned(), 1)].into_iter().collect()
+    );
+    assert_eq!(passports[1].malformed, vec!["stray".to_owned()]);
+    // A repeated key keeps the last value, but the replaced pair is kept too.
+    assert_eq!(passports[2].get("hgt"), Some(&"short".to_owned()));
+    assert_eq!(passports[2].repeated, vec!["hgt:tall".to_owned()]);
+    assert!(passports[1].repeated.is_empty());
+
+    assert_eq!(
+        to_csv(&schema, &passports, "cm"),
+        "passport,pid,hgt,height_cm,valid,duplicate_pid\n\
+         1,000000001,70in,177.8,true,true\n\
+         2,000000001,180cm,180.0,true,true\n\
+         3,12,short,,false,false"
+    );
+    assert_eq!(csv_value("say \"hi\", twice"), "\"say \"\"hi\"\", twice\"");
+
+    let json = to_json(&schema, &passports, "in");
+    assert_eq!(json.lines().count(), 5);
+    assert!(json.contains(
+        "{\"passport\": 2, \"fields\": {\"hgt\": \"180cm\", \"note\": \"\\\"a,b\\\"\", \"pid\": \"000000001\"}, \
+         \"height_in\": 70.9, \"valid\": true, \"duplicate_pid\": true, \"unknown_keys\": [\"note\"], \
+         \"malformed\": [\"stray\"], \"repeated\": []}"
+    ));
+    assert!(json.contains("\"height_in\": null"));
+    assert!(json.contains("\"repeated\": [\"hgt:tall\"]"));
 }
 
 pub fn puzzle2() {
-    let passports = match read_input_file() {
+    let (schema, passports) = match read_input() {
         Err(e) => {
             println!("{}", e);
             return;
@@ -224,6 +650,150 @@ pub fn puzzle2() {
         Ok(v) => v,
     };
 
-    let valid = passports.iter().filter(|&p| is_passport_valid2(p)).count();
+    let valid = passports
+        .iter()
+        .filter(|&p| check_passport(&schema, p).is_valid())
+        .count();
     println!("Puzzle 2: We have {} valid passports", valid);
 }
+
+// The schema given with `--schema`, defaults to input/day4_schema.txt.
+fn schema_option(options: &Options) -> Result<Schema, String> {
+    options
+        .get_or("schema", SCHEMA_FILE.to_owned())
+        .and_then(|path| read_schema(&path))
+}
+
+// Lists every passport that does not match the schema (`--schema`, defaults to
+// input/day4_schema.txt), with its missing and invalid fields.
+pub fn report(options: &Options) {
+    if let Some(option) = ["unit", "output"].iter().find(|o| options.has(o)) {
+        return eprintln!("--{} can only be used with --export", option);
+    }
+
+    let schema = match schema_option(options) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let passports = match read_input_file() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let mut valid = 0;
+    for (i, passport) in passports.iter().enumerate() {
+        let report = check_passport(&schema, passport);
+        if report.is_valid() {
+            valid += 1;
+            continue;
+        }
+
+        println!("Passport #{}:", i + 1);
+        if !report.missing.is_empty() {
+            println!("  missing: {}", report.missing.join(", "));
+        }
+        for (field, value, reason) in report.invalid {
+            println!("  {} '{}' {}", field, value, reason);
+        }
+    }
+
+    println!("{} of {} passport(s) are valid", valid, passports.len());
+    print_batch_issues(&schema, &passports);
+}
+
+// Things that are wrong with the batch as a whole, rather than with a single passport.
+fn print_batch_issues(schema: &Schema, passports: &[Passport]) {
+    for (pid, used_by) in duplicate_pids(passports) {
+        let numbers = used_by
+            .iter()
+            .map(|i| format!("#{}", i + 1))
+            .collect::<Vec<_>>();
+        println!("Duplicate pid {} in passports {}", pid, numbers.join(", "));
+    }
+    for (key, count) in unknown_keys(schema, passports) {
+        println!("Unknown key '{}' in {} passport(s)", key, count);
+    }
+    for (i, passport) in passports.iter().enumerate() {
+        for pair in &passport.malformed {
+            println!(
+                "Passport #{}: ignored '{}', it is not a key:value pair",
+                i + 1,
+                pair
+            );
+        }
+        for pair in &passport.repeated {
+            println!(
+                "Passport #{}: ignored '{}', the key is given again later",
+                i + 1,
+                pair
+            );
+        }
+    }
+}
+
+// Writes all passports as `--export csv` or `--export json`, checked against the `--schema` (like
+// `report`) and with heights in `--unit` (cm or in, defaults to cm), to stdout or the `--output`
+// file. Problems with the batch go to stderr.
+pub fn export(options: &Options) {
+    let (format, unit, output) = match options
+        .get::<String>("export")
+        .map(|f| f.unwrap_or_default())
+        .and_then(|f| match f.as_str() {
+            "csv" | "json" => Ok(f),
+            _ => Err(format!("Unknown export format '{}', use csv or json", f)),
+        })
+        .and_then(|f| {
+            options
+                .get_or("unit", "cm".to_owned())
+                .and_then(|u| match u.as_str() {
+                    "cm" | "in" => Ok((f, u)),
+                    _ => Err(format!("Unknown height unit '{}', use cm or in", u)),
+                })
+        })
+        .and_then(|(f, u)| options.get::<String>("output").map(|o| (f, u, o)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let (schema, passports) = match schema_option(options)
+        .and_then(|schema| read_input_file().map(|passports| (schema, passports)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let data = if format == "csv" {
+        to_csv(&schema, &passports, &unit)
+    } else {
+        to_json(&schema, &passports, &unit)
+    };
+
+    match output {
+        None => println!("{}", data),
+        Some(path) => {
+            if let Err(e) = write(&path, data + "\n") {
+                return eprintln!("Could not write {}: {}", path, e);
+            }
+            eprintln!("Wrote {} passport(s) to {}", passports.len(), path);
+        }
+    }
+
+    let duplicates = duplicate_pids(&passports);
+    if !duplicates.is_empty() {
+        eprintln!(
+            "{} pid(s) are used by more than one passport",
+            duplicates.len()
+        );
+    }
+    let unknown = unknown_keys(&schema, &passports);
+    if !unknown.is_empty() {
+        let keys = unknown.keys().cloned().collect::<Vec<_>>();
+        eprintln!("Found unknown keys: {}", keys.join(", "));
+    }
+    let repeated = passports.iter().map(|p| p.repeated.len()).sum::<usize>();
+    if repeated > 0 {
+        eprintln!("{} repeated key(s) replaced an earlier value", repeated);
+    }
+}
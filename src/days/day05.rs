@@ -1,4 +1,5 @@
 use crate::util::input::read_mapped_input;
+use crate::util::options::Options;
 use core::fmt;
 use std::collections::HashMap;
 use std::iter::FromIterator;
@@ -16,37 +17,133 @@ impl fmt::Display for Seat {
     }
 }
 
-fn parse_seat(location: String) -> Result<Seat, String> {
-    // The location is binary-encoded for a seat in one of the 128 rows, and 8 columns.
-    // The row is encoded as a string of 7 'F' or 'B' characters, where F means lower and B means upper half.
-    // Same for col, encoded using 3 'L' or 'R' characters, where L means lower and R means upper half.
-    let mut min_row = 0;
-    let mut max_row = 128; // Exclusive, but easier to divide by two
-
-    for i in 0..7 {
-        match location.chars().nth(i) {
-            Some('F') => max_row -= (max_row - min_row) / 2,
-            Some('B') => min_row += (max_row - min_row) / 2,
-            _ => continue, // Ignore unknowns
+// How seats are laid out in the plane, and how a boarding pass encodes them: a binary space
+// partition, where the first letter of a pair means the lower half and the second the upper half.
+// The row is encoded by `row_bits` row letters, followed by `col_bits` column letters.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Geometry {
+    row_bits: u32,
+    col_bits: u32,
+    row_letters: (char, char),
+    col_letters: (char, char),
+}
+
+// 128 rows with 'F'(ront) and 'B'(ack), 8 columns with 'L'(eft) and 'R'(ight).
+const STANDARD: Geometry = Geometry {
+    row_bits: 7,
+    col_bits: 3,
+    row_letters: ('F', 'B'),
+    col_letters: ('L', 'R'),
+};
+
+impl Geometry {
+    fn new(
+        row_bits: u32,
+        col_bits: u32,
+        row_letters: (char, char),
+        col_letters: (char, char),
+    ) -> Result<Geometry, String> {
+        // Seat IDs need to fit in an i32
+        if row_bits.checked_add(col_bits).is_none_or(|bits| bits > 30) {
+            return Err(format!(
+                "A plane with {} row and {} column bits is too big",
+                row_bits, col_bits
+            ));
         }
+
+        let letters = [row_letters.0, row_letters.1, col_letters.0, col_letters.1];
+        if (1..4).any(|i| letters[..i].contains(&letters[i])) {
+            return Err(format!(
+                "The letters {}{} and {}{} should all be different",
+                row_letters.0, row_letters.1, col_letters.0, col_letters.1
+            ));
+        }
+
+        Ok(Geometry {
+            row_bits,
+            col_bits,
+            row_letters,
+            col_letters,
+        })
     }
 
-    let mut min_col = 0;
-    let mut max_col = 8; // Exclusive
+    fn rows(&self) -> i32 {
+        1 << self.row_bits
+    }
+
+    fn cols(&self) -> i32 {
+        1 << self.col_bits
+    }
 
-    for i in 7..10 {
-        match location.chars().nth(i) {
-            Some('L') => max_col -= (max_col - min_col) / 2,
-            Some('R') => min_col += (max_col - min_col) / 2,
-            _ => continue,
+    fn seat(&self, row: i32, col: i32) -> Seat {
+        Seat {
+            row,
+            col,
+            id: row * self.cols() + col,
         }
     }
 
-    Ok(Seat {
-        row: min_row,
-        col: min_col,
-        id: min_row * 8 + min_col,
-    })
+    fn decode(&self, pass: &str) -> Result<Seat, String> {
+        let chars = pass.chars().collect::<Vec<_>>();
+        let bits = (self.row_bits + self.col_bits) as usize;
+        if chars.len() != bits {
+            return Err(format!(
+                "Boarding pass '{}' should be {} characters long",
+                pass, bits
+            ));
+        }
+
+        let (row_part, col_part) = chars.split_at(self.row_bits as usize);
+        let to_number = |part: &[char], (lower, upper): (char, char)| {
+            part.iter().try_fold(0, |n, &c| match c {
+                c if c == lower => Ok(n * 2),
+                c if c == upper => Ok(n * 2 + 1),
+                c => Err(format!(
+                    "Invalid character '{}' in boarding pass '{}', expected {} or {}",
+                    c, pass, lower, upper
+                )),
+            })
+        };
+
+        Ok(self.seat(
+            to_number(row_part, self.row_letters)?,
+            to_number(col_part, self.col_letters)?,
+        ))
+    }
+
+    fn encode(&self, seat: &Seat) -> Result<String, String> {
+        if seat.row < 0 || seat.row >= self.rows() || seat.col < 0 || seat.col >= self.cols() {
+            return Err(format!(
+                "{} does not exist in a plane with {} rows and {} columns",
+                seat,
+                self.rows(),
+                self.cols()
+            ));
+        }
+
+        let to_letters = |n: i32, bits: u32, (lower, upper): (char, char)| {
+            (0..bits)
+                .rev()
+                .map(|bit| if n & (1 << bit) == 0 { lower } else { upper })
+                .collect::<String>()
+        };
+
+        Ok(to_letters(seat.row, self.row_bits, self.row_letters)
+            + &to_letters(seat.col, self.col_bits, self.col_letters))
+    }
+
+    // Seat IDs are row * cols + col, so this maps every ID in the plane back to its seat.
+    fn seat_by_id(&self, id: i32) -> Result<Seat, String> {
+        if id < 0 || id >= self.rows() * self.cols() {
+            return Err(format!("Seat ID {} does not exist in this plane", id));
+        }
+
+        Ok(self.seat(id / self.cols(), id % self.cols()))
+    }
+}
+
+fn parse_seat(location: String) -> Result<Seat, String> {
+    STANDARD.decode(&location)
 }
 
 #[test]
@@ -98,20 +195,87 @@ pub fn puzzle1() {
     println!("Puzzle 1, max seat ID = {}", max_seat_by_id.id);
 }
 
-fn get_free_seats(seats: &HashMap<i32, Seat>) -> Vec<Seat> {
-    let mut result = vec![];
+// Free seats in between two taken ones; on a full flight that's where the missing passengers sit.
+// Free seats at the front and back of the plane (that may not exist) are not gaps. Only looks at
+// the taken seats, so it doesn't matter how big the plane is.
+fn find_gaps(geometry: &Geometry, seats: &HashMap<i32, Seat>) -> Vec<Seat> {
+    let mut ids = seats.keys().copied().collect::<Vec<_>>();
+    ids.sort_unstable();
 
-    for row in 0..128 {
-        for col in 0..8 {
-            let id = row * 8 + col;
-            match seats.get(&id) {
-                None => result.push(Seat { row, col, id }),
-                _ => continue,
-            }
-        }
+    ids.windows(2)
+        .filter(|pair| pair[1] - pair[0] == 2)
+        .filter_map(|pair| geometry.seat_by_id(pair[0] + 1).ok())
+        .collect()
+}
+
+#[test]
+fn test_geometry() {
+    assert_eq!(
+        STANDARD.encode(&STANDARD.seat(44, 5)),
+        Ok("FBFBBFFRLR".to_owned())
+    );
+    for id in 0..1024 {
+        let seat = STANDARD.seat_by_id(id).unwrap();
+        assert_eq!(STANDARD.decode(&STANDARD.encode(&seat).unwrap()), Ok(seat));
     }
 
-    result
+    assert!(STANDARD.decode("FBFBBFFRL").is_err());
+    assert!(STANDARD.decode("FBFBBFFRLRR").is_err());
+    assert_eq!(
+        STANDARD.decode("FBFXBFFRLR"),
+        Err("Invalid character 'X' in boarding pass 'FBFXBFFRLR', expected F or B".to_owned())
+    );
+    assert!(STANDARD.decode("FBFBBFFRLB").is_err());
+    assert!(STANDARD.encode(&STANDARD.seat(128, 0)).is_err());
+    assert!(STANDARD.seat_by_id(1024).is_err());
+
+    assert!(Geometry::new(3, 2, ('F', 'B'), ('B', 'R')).is_err());
+    assert!(Geometry::new(20, 20, ('F', 'B'), ('L', 'R')).is_err());
+    // Used to overflow while adding up the bits.
+    assert_eq!(
+        Geometry::new(u32::MAX, 2, ('F', 'B'), ('L', 'R')),
+        Err("A plane with 4294967295 row and 2 column bits is too big".to_owned())
+    );
+
+    // A small plane: 4 rows of 2 seats, using 0/1 and a/b.
+    let small = Geometry::new(2, 1, ('0', '1'), ('a', 'b')).unwrap();
+    assert_eq!(
+        small.decode("10b"),
+        Ok(Seat {
+            row: 2,
+            col: 1,
+            id: 5
+        })
+    );
+    assert_eq!(small.encode(&small.seat(3, 0)), Ok("11a".to_owned()));
+
+    let taken = ["00a", "00b", "01b", "10a", "11a"]
+        .iter()
+        .map(|p| small.decode(p).map(|s| (s.id, s)))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .unwrap();
+    assert_eq!(
+        find_gaps(&small, &taken)
+            .iter()
+            .map(|s| s.id)
+            .collect::<Vec<_>>(),
+        vec![2, 5]
+    );
+
+    // The biggest plane allowed, with only a few boarding passes.
+    let big = Geometry::new(20, 10, ('F', 'B'), ('L', 'R')).unwrap();
+    let taken = [0, 2, 4, 5, 1 << 29, (1 << 29) + 2, (1 << 30) - 1]
+        .iter()
+        .map(|&id| big.seat_by_id(id).map(|s| (s.id, s)))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .unwrap();
+    assert_eq!(
+        find_gaps(&big, &taken)
+            .iter()
+            .map(|s| s.id)
+            .collect::<Vec<_>>(),
+        vec![1, 3, (1 << 29) + 1]
+    );
 }
 
 pub fn puzzle2() {
@@ -126,15 +290,7 @@ pub fn puzzle2() {
     // Find free seat in the plane, some at the front & end do not exist, so those shouldn't be our
     // seat. (To check, the seats with ID n-1 and n+1 (n = your seat) should exist)
 
-    let free_seats = get_free_seats(&seats);
-    let mut existing_free_seats = vec![];
-
-    for seat in free_seats {
-        match (seats.get(&(seat.id - 1)), seats.get(&(seat.id + 1))) {
-            (Some(_), Some(_)) => existing_free_seats.push(seat),
-            _ => continue,
-        }
-    }
+    let existing_free_seats = find_gaps(&STANDARD, &seats);
 
     match existing_free_seats.len() {
         1 => println!("Puzzle 2: Found > {}", existing_free_seats[0]),
@@ -148,3 +304,61 @@ pub fn puzzle2() {
         ),
     }
 }
+
+fn letter_pair(
+    options: &Options,
+    name: &str,
+    default: (char, char),
+) -> Result<(char, char), String> {
+    match options.get::<String>(name)? {
+        None => Ok(default),
+        Some(letters) => match letters.chars().collect::<Vec<_>>()[..] {
+            [lower, upper] => Ok((lower, upper)),
+            _ => Err(format!("--{} should be two letters, like FB", name)),
+        },
+    }
+}
+
+// Decodes the boarding passes for a plane with `--rows` and `--cols` bits (7 and 3), using
+// `--row-letters` and `--col-letters` (FB and LR); listing the highest ID and all gaps. With
+// `--encode <id>`, prints the boarding pass for that seat instead.
+pub fn codec(options: &Options) {
+    let geometry = match options.get_or("rows", STANDARD.row_bits).and_then(|rows| {
+        let cols = options.get_or("cols", STANDARD.col_bits)?;
+        let row_letters = letter_pair(options, "row-letters", STANDARD.row_letters)?;
+        let col_letters = letter_pair(options, "col-letters", STANDARD.col_letters)?;
+        Geometry::new(rows, cols, row_letters, col_letters)
+    }) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    if options.has("encode") {
+        match options
+            .get::<i32>("encode")
+            .and_then(|id| geometry.seat_by_id(id.unwrap_or_default()))
+            .and_then(|seat| geometry.encode(&seat).map(|pass| (seat, pass)))
+        {
+            Err(e) => eprintln!("{}", e),
+            Ok((seat, pass)) => println!("{}: {}", seat, pass),
+        }
+        return;
+    }
+
+    let seats = match read_mapped_input(5, |pass| geometry.decode(&pass)) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => HashMap::from_iter(v.into_iter().map(|s| (s.id, s))),
+    };
+
+    match seats.values().max_by_key(|s| s.id) {
+        None => return println!("No boarding passes"),
+        Some(seat) => println!("Highest: {}", seat),
+    }
+    for seat in find_gaps(&geometry, &seats) {
+        println!(
+            "Gap: {} ({})",
+            seat,
+            geometry.encode(&seat).unwrap_or_default()
+        );
+    }
+}
@@ -1,129 +1,251 @@
 use crate::util::input::read_raw_input;
+use crate::util::options::Options;
+
+// The questions a person answered "yes" to, as a bitset: bit 0 is question 'a', bit 25 is 'z'.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+struct Answers(u32);
+
+const QUESTIONS: usize = 26;
+
+impl Answers {
+    fn parse(line: &str) -> Result<Answers, String> {
+        line.chars()
+            .try_fold(Answers::default(), |answers, c| match c {
+                'a'..='z' => Ok(Answers(answers.0 | 1 << (c as u32 - 'a' as u32))),
+                _ => Err(format!("Invalid question '{}' in answers '{}'", c, line)),
+            })
+    }
+
+    fn union(self, other: Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+
+    fn intersection(self, other: Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+
+    fn symmetric_difference(self, other: Answers) -> Answers {
+        Answers(self.0 ^ other.0)
+    }
+
+    fn contains(self, question: usize) -> bool {
+        self.0 & (1 << question) != 0
+    }
+
+    fn count(self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
 
 struct Group {
-    answers: Vec<String>,
+    people: Vec<Answers>,
 }
 
-fn get_groups(input: &String) -> Vec<Group> {
+fn get_groups(input: &str) -> Result<Vec<Group>, String> {
     input
         .split("\n\n") // Groups are separated by a blank line
-        .map(|d| Group {
-            answers: d.split("\n").map(str::to_owned).collect(), // Each line contains the answers from one person
+        // More blank lines in a row (or at the end) are not a group of people that answered nothing
+        .filter(|d| !d.trim().is_empty())
+        .enumerate()
+        .map(|(i, d)| {
+            // Each line contains the answers from one person
+            d.split('\n')
+                .filter(|line| !line.is_empty())
+                .map(Answers::parse)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|people| Group { people })
+                .map_err(|e| format!("Group {}: {}", i + 1, e))
         })
         .collect()
 }
 
-fn get_group_answer_count(group: &Group) -> i32 {
-    let mut result = vec![false; 26];
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Query {
+    // Questions anyone in the group answered
+    Union,
+    // Questions everyone in the group answered
+    Intersection,
+    // Questions an odd number of people in the group answered
+    SymmetricDifference,
+    // Questions at least this many people in the group answered
+    AtLeast(usize),
+}
 
-    for answer in &group.answers {
-        for char in answer.chars() {
-            let index = char.to_digit(36).unwrap() - 10;
-            result[index as usize] = true;
+impl Query {
+    fn parse(query: &str) -> Result<Query, String> {
+        match query {
+            "union" | "any" => Ok(Query::Union),
+            "intersection" | "all" => Ok(Query::Intersection),
+            "xor" | "symmetric-difference" => Ok(Query::SymmetricDifference),
+            _ => match query.strip_prefix("at-least:").map(str::parse) {
+                Some(Ok(n)) => Ok(Query::AtLeast(n)),
+                _ => Err(format!(
+                    "Unknown query '{}', expected union, intersection, xor or at-least:<n>",
+                    query
+                )),
+            },
         }
     }
+}
+
+impl Group {
+    fn query(&self, query: Query) -> Answers {
+        let people = self.people.iter().copied();
+        match query {
+            Query::Union => people.fold(Answers::default(), Answers::union),
+            // Nobody in a group without people answered anything
+            Query::Intersection => people.reduce(Answers::intersection).unwrap_or_default(),
+            Query::SymmetricDifference => {
+                people.fold(Answers::default(), Answers::symmetric_difference)
+            }
+            Query::AtLeast(n) => {
+                let counts = self.histogram();
+                Answers(
+                    (0..QUESTIONS)
+                        .filter(|&q| counts[q] >= n)
+                        .fold(0, |bits, q| bits | 1 << q),
+                )
+            }
+        }
+    }
+
+    // How many people in the group answered every question.
+    fn histogram(&self) -> [usize; QUESTIONS] {
+        let mut counts = [0; QUESTIONS];
+        for answers in &self.people {
+            for (q, count) in counts.iter_mut().enumerate() {
+                if answers.contains(q) {
+                    *count += 1;
+                }
+            }
+        }
+        counts
+    }
+}
 
-    result.iter().map(|&v| if v { 1 } else { 0 }).sum()
+fn get_group_answer_count(group: &Group) -> usize {
+    group.query(Query::Union).count()
+}
+
+#[cfg(test)]
+fn group(people: &[&str]) -> Group {
+    Group {
+        people: people.iter().map(|p| Answers::parse(p).unwrap()).collect(),
+    }
 }
 
 #[test]
 fn test_get_group_answer_count() {
-    assert_eq!(
-        get_group_answer_count(&Group {
-            answers: vec!["abc".to_owned()]
-        }),
-        3
-    );
-    assert_eq!(
-        get_group_answer_count(&Group {
-            answers: vec!["abc".to_owned(), "bcd".to_owned()]
-        }),
-        4
-    );
-    assert_eq!(
-        get_group_answer_count(&Group {
-            answers: vec!["a".to_owned(), "a".to_owned(), "a".to_owned()]
-        }),
-        1
-    );
-    assert_eq!(
-        get_group_answer_count(&Group {
-            answers: vec!["a".to_owned(), "x".to_owned(), "z".to_owned()]
-        }),
-        3
-    );
+    assert_eq!(get_group_answer_count(&group(&["abc"])), 3);
+    assert_eq!(get_group_answer_count(&group(&["abc", "bcd"])), 4);
+    assert_eq!(get_group_answer_count(&group(&["a", "a", "a"])), 1);
+    assert_eq!(get_group_answer_count(&group(&["a", "x", "z"])), 3);
 }
 
 pub fn puzzle1() {
     // For each group, count the unique answers
     // Puzzle 1 output is the sum of those
-    let groups = match read_raw_input(6) {
+    let groups = match read_raw_input(6).and_then(|v| get_groups(&v)) {
         Err(e) => {
             println!("{}", e);
             return;
         }
-        Ok(v) => get_groups(&v),
+        Ok(v) => v,
     };
 
-    let answer: i32 = groups.iter().map(get_group_answer_count).sum();
+    let answer: usize = groups.iter().map(get_group_answer_count).sum();
     println!("Puzzle 1 answer: {}", answer);
 }
 
 fn get_group_mutual_answer_count(group: &Group) -> usize {
     // Find the number of answers everyone in the group answered
-    let mut results: Vec<usize> = vec![0; 26];
-
-    for answer in &group.answers {
-        for char in answer.chars() {
-            let index = char.to_digit(36).unwrap() - 10;
-            results[index as usize] += 1
-        }
-    }
-
-    results
-        .iter()
-        .filter(|&n| n.eq(&group.answers.len()))
-        .count()
+    group.query(Query::Intersection).count()
 }
 
 #[test]
 fn test_get_group_mutual_answer_count() {
+    assert_eq!(get_group_mutual_answer_count(&group(&["abc"])), 3);
+    assert_eq!(get_group_mutual_answer_count(&group(&["abc", "bcd"])), 2);
+    assert_eq!(get_group_mutual_answer_count(&group(&["a", "a", "a"])), 1);
+    assert_eq!(get_group_mutual_answer_count(&group(&["a", "x", "z"])), 0);
+}
+
+#[test]
+fn test_queries() {
+    let g = group(&["abc", "bcd", "cx"]);
+    let count = |q| g.query(Query::parse(q).unwrap()).count();
+
+    assert_eq!(count("union"), 5);
+    assert_eq!(count("all"), 1);
+    // a, c, d and x were answered by one or three people
+    assert_eq!(count("xor"), 4);
+    assert_eq!(count("at-least:2"), 2);
+    assert_eq!(count("at-least:0"), 26);
+    assert_eq!(g.histogram()[..4], [1, 2, 3, 1]);
+    assert!(Query::parse("at-least:x").is_err());
+
     assert_eq!(
-        get_group_mutual_answer_count(&Group {
-            answers: vec!["abc".to_owned()]
-        }),
-        3
-    );
-    assert_eq!(
-        get_group_mutual_answer_count(&Group {
-            answers: vec!["abc".to_owned(), "bcd".to_owned()]
-        }),
-        2
-    );
-    assert_eq!(
-        get_group_mutual_answer_count(&Group {
-            answers: vec!["a".to_owned(), "a".to_owned(), "a".to_owned()]
-        }),
-        1
-    );
-    assert_eq!(
-        get_group_mutual_answer_count(&Group {
-            answers: vec!["a".to_owned(), "x".to_owned(), "z".to_owned()]
-        }),
-        0
+        get_groups("ab\nb\n\nc1").err(),
+        Some("Group 2: Invalid question '1' in answers 'c1'".to_owned())
     );
+    assert!(Answers::parse("A").is_err());
+    assert_eq!(get_groups("ab\nb\n\nc\n").unwrap()[1].people.len(), 1);
+
+    // Extra blank lines don't add empty groups, which would answer everything with intersection.
+    let groups = get_groups("ab\nb\n\n\n\nc\n\n").unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[1].query(Query::Intersection).count(), 1);
+    assert_eq!(get_groups("\n\n").unwrap().len(), 0);
+    assert_eq!(group(&[]).query(Query::Intersection).count(), 0);
 }
 
 pub fn puzzle2() {
     // Oops. We needed the answers _everyone_ in the group answered!
-    let groups = match read_raw_input(6) {
+    let groups = match read_raw_input(6).and_then(|v| get_groups(&v)) {
         Err(e) => {
             println!("{}", e);
             return;
         }
-        Ok(v) => get_groups(&v),
+        Ok(v) => v,
     };
 
     let answer: usize = groups.iter().map(get_group_mutual_answer_count).sum();
     println!("Puzzle 2 answer: {}", answer);
 }
+
+// Sums the answers matching `--query` (union, intersection, xor or at-least:<n>) over all
+// groups. With `--histogram`, also lists how many people answered each question.
+pub fn query(options: &Options) {
+    let query = match options
+        .get_or("query", "union".to_owned())
+        .and_then(|q| Query::parse(&q))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let groups = match read_raw_input(6).and_then(|v| get_groups(&v)) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let total: usize = groups.iter().map(|g| g.query(query).count()).sum();
+    println!(
+        "{:?}: {} answers over {} groups",
+        query,
+        total,
+        groups.len()
+    );
+
+    if options.has("histogram") {
+        let mut counts = [0; QUESTIONS];
+        for group in &groups {
+            for (total, count) in counts.iter_mut().zip(group.histogram().iter()) {
+                *total += count;
+            }
+        }
+        for (q, count) in counts.iter().enumerate() {
+            println!("{}: {}", (b'a' + q as u8) as char, count);
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use crate::util::input::read_raw_input;
-use std::collections::HashMap;
+use crate::util::options::Options;
+use crate::util::symbols::{Interner, Symbol};
+use std::collections::{HashMap, HashSet};
+use std::fs::write;
 
 #[derive(Eq, PartialEq, Debug)]
 struct Content {
-    bag_type: String,
-    amount: i32,
+    bag_type: Symbol,
+    amount: u32,
 }
 
 #[derive(PartialEq, Debug)]
@@ -20,7 +23,7 @@ enum ParseState {
     EOL,
 }
 
-fn parse_line(line: &str) -> Result<(String, Vec<Content>), String> {
+fn parse_line(line: &str, names: &mut Interner) -> Result<(Symbol, Vec<Content>), String> {
     let words: Vec<_> = line.split(" ").collect();
 
     let mut state = ParseState::Init;
@@ -81,7 +84,7 @@ fn parse_line(line: &str) -> Result<(String, Vec<Content>), String> {
                 }
 
                 contents.push(Content {
-                    bag_type: content_bag_type,
+                    bag_type: names.intern(&content_bag_type),
                     amount: content_amount,
                 });
                 content_bag_type = "".to_owned();
@@ -96,7 +99,7 @@ fn parse_line(line: &str) -> Result<(String, Vec<Content>), String> {
                 }
 
                 contents.push(Content {
-                    bag_type: content_bag_type,
+                    bag_type: names.intern(&content_bag_type),
                     amount: content_amount,
                 });
                 content_bag_type = "".to_owned();
@@ -113,7 +116,7 @@ fn parse_line(line: &str) -> Result<(String, Vec<Content>), String> {
                     }
                     ParseState::ContainToken | ParseState::ContentComma => {
                         // We expect this word to be numeric.
-                        content_amount = match word.parse::<i32>() {
+                        content_amount = match word.parse::<u32>() {
                             Err(e) => return Err(format!("{}", e)),
                             Ok(v) => v,
                         };
@@ -130,72 +133,258 @@ fn parse_line(line: &str) -> Result<(String, Vec<Content>), String> {
         }
     }
 
-    Ok((bag_type, contents))
+    Ok((names.intern(&bag_type), contents))
 }
 
 #[test]
 fn test_parse_line() {
+    let mut names = Interner::new();
+    let blue = names.intern("shiny blue");
+    let green = names.intern("mat green");
+    let silver = names.intern("silver");
+
     assert_eq!(
-        parse_line("shiny blue bags contain no other bags."),
-        Ok(("shiny blue".to_owned(), vec![]))
+        parse_line("shiny blue bags contain no other bags.", &mut names),
+        Ok((blue, vec![]))
     );
     assert_eq!(
-        parse_line("shiny blue bags contain 2 mat green bags, 1 silver bag."),
+        parse_line(
+            "shiny blue bags contain 2 mat green bags, 1 silver bag.",
+            &mut names
+        ),
         Ok((
-            "shiny blue".to_owned(),
+            blue,
             vec![
                 Content {
-                    bag_type: "mat green".to_owned(),
+                    bag_type: green,
                     amount: 2
                 },
                 Content {
-                    bag_type: "silver".to_owned(),
+                    bag_type: silver,
                     amount: 1
                 }
             ]
         ))
     );
     assert_eq!(
-        parse_line("shiny blue bags contain 42 mat green bags."),
+        parse_line("shiny blue bags contain 42 mat green bags.", &mut names),
         Ok((
-            "shiny blue".to_owned(),
+            blue,
             vec![Content {
-                bag_type: "mat green".to_owned(),
+                bag_type: green,
                 amount: 42
             }]
         ))
     );
+    assert_eq!(names.get("bags"), None);
 }
 
-fn read_input_data() -> Result<HashMap<String, Vec<Content>>, String> {
-    let data = read_raw_input(7)?;
+#[test]
+fn test_parse_line_fuzz() {
+    use crate::util::fuzz::{check, Rng};
+
+    fn colour(rng: &mut Rng) -> String {
+        let shades = ["shiny", "dark", "pale", "vibrant"];
+        let colours = ["gold", "red", "olive", "plum", "teal"];
+        format!("{} {}", rng.pick(&shades), rng.pick(&colours))
+    }
+
+    check(
+        "day 7 rules round-trip",
+        |rng| {
+            let bag = colour(rng);
+            let contents = (0..rng.below(4))
+                .map(|_| (rng.range(1, 20) as u32, colour(rng)))
+                .collect::<Vec<_>>();
+            (bag, contents)
+        },
+        |(bag, contents)| {
+            let line = if contents.is_empty() {
+                format!("{} bags contain no other bags.", bag)
+            } else {
+                let parts = contents
+                    .iter()
+                    .map(|(n, c)| format!("{} {} {}", n, c, if *n == 1 { "bag" } else { "bags" }))
+                    .collect::<Vec<_>>();
+                format!("{} bags contain {}.", bag, parts.join(", "))
+            };
+
+            let mut names = Interner::new();
+            let (parsed_bag, parsed) = parse_line(&line, &mut names)?;
+            let parsed = parsed
+                .iter()
+                .map(|c| (c.amount, names.resolve(c.bag_type).to_owned()))
+                .collect::<Vec<_>>();
+            if names.resolve(parsed_bag) != bag || &parsed != contents {
+                return Err(format!("'{}' parsed as {:?}", line, parsed));
+            }
+            Ok(())
+        },
+    );
+
+    check(
+        "day 7 parser does not panic",
+        |rng| {
+            let words = [
+                "bags",
+                "bag,",
+                "bags.",
+                "contain",
+                "no",
+                "other",
+                "2",
+                "shiny gold",
+            ];
+            (0..rng.below(10))
+                .map(|_| {
+                    if rng.chance(70) {
+                        rng.pick(&words).to_string()
+                    } else {
+                        rng.noise(6)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        |line| {
+            let _ = parse_line(line, &mut Interner::new());
+            Ok(())
+        },
+    );
+}
+
+fn parse_rules(data: &str, names: &mut Interner) -> Result<Vec<(Symbol, Vec<Content>)>, String> {
     // Each line contains either:
     // - <bag_type> bags contain <# bag_type>[, …].
     // - <bag_type> bags contain no other bags.
 
     // We'll parse the input line-by-line, word-by-word, trying to make sense of it.
-    let lines: Vec<_> = data.split("\n").collect();
+    data.split("\n")
+        .enumerate()
+        .map(|(i, line)| parse_line(line, names).map_err(|e| format!("Line {}: {}", i + 1, e)))
+        .collect()
+}
 
-    let mut result: HashMap<String, Vec<Content>> = HashMap::with_capacity(lines.len());
+#[derive(Eq, PartialEq, Debug)]
+enum Problem {
+    // Every bag in the cycle contains the next one, and the last contains the first.
+    Cycle(Vec<Symbol>),
+    Dangling { bag: Symbol, missing: Symbol },
+    Duplicate(Symbol),
+    ZeroAmount { bag: Symbol, content: Symbol },
+}
 
-    for line in lines {
-        let (bag_type, contents) = match parse_line(line) {
-            Err(e) => return Err(e),
-            Ok(v) => v,
-        };
+impl Problem {
+    fn describe(&self, names: &Interner) -> String {
+        match self {
+            Problem::Cycle(bags) => format!(
+                "Bags containing themselves: {} > {}",
+                bags.iter()
+                    .map(|b| names.resolve(*b))
+                    .collect::<Vec<_>>()
+                    .join(" > "),
+                names.resolve(bags[0])
+            ),
+            Problem::Dangling { bag, missing } => format!(
+                "{} bags contain {} bags, but there is no rule for those",
+                names.resolve(*bag),
+                names.resolve(*missing)
+            ),
+            Problem::Duplicate(bag) => {
+                format!(
+                    "There is more than one rule for {} bags",
+                    names.resolve(*bag)
+                )
+            }
+            Problem::ZeroAmount { bag, content } => format!(
+                "{} bags contain 0 {} bags",
+                names.resolve(*bag),
+                names.resolve(*content)
+            ),
+        }
+    }
+}
 
-        // Create entry using bag_type:
-        match result.insert(bag_type.to_owned(), contents) {
-            Some(_) => return Err(format!("Already read an entry for {}", bag_type)),
-            None => {}
+// Checks the rules before we use them, so we don't have to run into these halfway through a
+// search (or, for cycles, not at all).
+fn validate_rules(rules: &[(Symbol, Vec<Content>)]) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut map: HashMap<Symbol, &Vec<Content>> = HashMap::with_capacity(rules.len());
+
+    for (bag, contents) in rules {
+        if map.insert(*bag, contents).is_some() {
+            problems.push(Problem::Duplicate(*bag));
+        }
+    }
+
+    for (bag, contents) in rules {
+        for content in contents.iter() {
+            if !map.contains_key(&content.bag_type) {
+                problems.push(Problem::Dangling {
+                    bag: *bag,
+                    missing: content.bag_type,
+                });
+            }
+            if content.amount == 0 {
+                problems.push(Problem::ZeroAmount {
+                    bag: *bag,
+                    content: content.bag_type,
+                });
+            }
+        }
+    }
+
+    // Depth first search; a bag we find again while it is still on our path closes a cycle.
+    fn visit(
+        bag: Symbol,
+        map: &HashMap<Symbol, &Vec<Content>>,
+        path: &mut Vec<Symbol>,
+        done: &mut HashSet<Symbol>,
+        problems: &mut Vec<Problem>,
+    ) {
+        if done.contains(&bag) {
+            return;
+        }
+        if let Some(i) = path.iter().position(|b| *b == bag) {
+            problems.push(Problem::Cycle(path[i..].to_vec()));
+            return;
+        }
+
+        path.push(bag);
+        for content in map.get(&bag).into_iter().flat_map(|c| c.iter()) {
+            visit(content.bag_type, map, path, done, problems);
         }
+        path.pop();
+        done.insert(bag);
     }
 
-    Ok(result)
+    let mut done = HashSet::new();
+    for (bag, _) in rules {
+        visit(*bag, &map, &mut vec![], &mut done, &mut problems);
+    }
+
+    problems
+}
+
+fn read_input_data() -> Result<(Interner, HashMap<Symbol, Vec<Content>>), String> {
+    let data = read_raw_input(7)?;
+    let mut names = Interner::new();
+    let rules = parse_rules(&data, &mut names)?;
+
+    let problems = validate_rules(&rules);
+    if !problems.is_empty() {
+        return Err(problems
+            .iter()
+            .map(|p| p.describe(&names))
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    Ok((names, rules.into_iter().collect()))
 }
 
 pub fn puzzle1() {
-    let bags = match read_input_data() {
+    let (names, bags) = match read_input_data() {
         Err(e) => {
             println!("{}", e);
             return;
@@ -203,16 +392,29 @@ pub fn puzzle1() {
         Ok(v) => v,
     };
 
-    // We need to find the number of (unique) bag types that can contain a shiny gold bag.
-    // We'll need to find all bags where the content defined a 'shiny gold' bag.
+    let shiny_gold = match names.get("shiny gold") {
+        None => return println!("There are no rules for shiny gold bags"),
+        Some(v) => v,
+    };
+
+    println!(
+        "Puzzle 1: I've visited {} bags that should be able to contain a shiny gold bag",
+        find_containers(&bags, shiny_gold).len()
+    );
+}
+
+// Finds all (unique) bag types that can, directly or indirectly, contain the given bag.
+fn find_containers(bags: &HashMap<Symbol, Vec<Content>>, bag: Symbol) -> HashSet<Symbol> {
+    // We'll need to find all bags where the content defined our bag.
     // Then, we'll iterate again on bags containing those bags (ignored the ones we've already seen)
-    let mut seen: Vec<String> = vec![];
-    let mut search: Vec<String> = vec!["shiny gold".to_owned()];
+    let mut seen: HashSet<Symbol> = HashSet::new();
+    let mut search: HashSet<Symbol> = HashSet::new();
+    search.insert(bag);
 
     loop {
-        let mut new_search: Vec<String> = vec![];
-        for (key, value) in &bags {
-            if seen.contains(&key) {
+        let mut new_search: HashSet<Symbol> = HashSet::new();
+        for (key, value) in bags {
+            if seen.contains(key) {
                 continue; // Already searched before
             }
 
@@ -220,70 +422,358 @@ pub fn puzzle1() {
                 continue; // No contents of interest currently.
             }
 
-            seen.push(key.to_string());
-            new_search.push(key.to_string());
+            seen.insert(*key);
+            new_search.insert(*key);
         }
 
-        if new_search.len() == 0 {
+        if new_search.is_empty() {
             break;
         }
         search = new_search;
     }
 
-    println!(
-        "Puzzle 1: I've visited {} bags that should be able to contain a shiny gold bag",
-        seen.len()
-    );
+    seen
 }
 
-fn get_bag_count(map: &HashMap<String, Vec<Content>>, bag: &String) -> Result<i32, String> {
-    let contents = match map.get(bag) {
-        None => {
-            return Err(format!("Needed to find {}, but not found in input", bag));
+// Finds all (unique) bag types that the given bag has to contain, directly or indirectly.
+fn find_contents(bags: &HashMap<Symbol, Vec<Content>>, bag: Symbol) -> HashSet<Symbol> {
+    let mut seen: HashSet<Symbol> = HashSet::new();
+    let mut search = vec![bag];
+
+    while let Some(next) = search.pop() {
+        for content in bags.get(&next).into_iter().flatten() {
+            if seen.insert(content.bag_type) {
+                search.push(content.bag_type);
+            }
+        }
+    }
+
+    seen
+}
+
+// The longest chain of bags inside bags, starting with the given bag. The chain for every bag type
+// is only searched once, as bags share a lot of their contents.
+fn find_deepest_chain(
+    bags: &HashMap<Symbol, Vec<Content>>,
+    names: &Interner,
+    bag: Symbol,
+) -> Result<Vec<Symbol>, String> {
+    fn search(
+        bags: &HashMap<Symbol, Vec<Content>>,
+        names: &Interner,
+        bag: Symbol,
+        memo: &mut HashMap<Symbol, Option<Vec<Symbol>>>,
+    ) -> Result<Vec<Symbol>, String> {
+        match memo.get(&bag) {
+            Some(Some(chain)) => return Ok(chain.clone()),
+            Some(None) => {
+                return Err(format!(
+                    "{} bags end up containing themselves",
+                    names.resolve(bag)
+                ))
+            }
+            None => {}
+        }
+        let contents = bags.get(&bag).ok_or(format!(
+            "Needed to find {}, but not found in input",
+            names.resolve(bag)
+        ))?;
+
+        // Mark the bag as in progress, so a cycle is an error rather than a stack overflow.
+        memo.insert(bag, None);
+        let mut deepest = vec![];
+        for content in contents {
+            let chain = search(bags, names, content.bag_type, memo)?;
+            if chain.len() > deepest.len() {
+                deepest = chain;
+            }
+        }
+
+        deepest.insert(0, bag);
+        memo.insert(bag, Some(deepest.clone()));
+        Ok(deepest)
+    }
+
+    search(bags, names, bag, &mut HashMap::new())
+}
+
+fn sorted_names(names: &Interner, bags: &HashSet<Symbol>) -> Vec<String> {
+    let mut result = bags
+        .iter()
+        .map(|b| names.resolve(*b).to_owned())
+        .collect::<Vec<_>>();
+    result.sort();
+    result
+}
+
+// Writes the rules as a Graphviz digraph, with an edge from every bag to the bags it contains
+// (labelled and weighted by the amount). With a `root`, only that bag and its contents are
+// included.
+fn to_dot(bags: &HashMap<Symbol, Vec<Content>>, names: &Interner, root: Option<Symbol>) -> String {
+    let included: HashSet<Symbol> = match root {
+        None => bags.keys().copied().collect(),
+        Some(root) => {
+            let mut contents = find_contents(bags, root);
+            contents.insert(root);
+            contents
         }
-        Some(c) => c,
     };
 
-    let mut amount = 0;
-    for content in contents {
-        amount += content.amount + (content.amount * get_bag_count(map, &content.bag_type)?);
+    let quote = |bag: Symbol| format!("\"{}\"", names.resolve(bag).replace('"', "\\\""));
+    let mut lines = vec![];
+    for bag in &included {
+        let contents = bags.get(bag).map(Vec::as_slice).unwrap_or_default();
+        if contents.is_empty() {
+            lines.push(format!("  {};", quote(*bag)));
+        }
+        for content in contents {
+            lines.push(format!(
+                "  {} -> {} [label={}, weight={}];",
+                quote(*bag),
+                quote(content.bag_type),
+                content.amount,
+                content.amount
+            ));
+        }
     }
+    // Sorted, so the output doesn't change between runs.
+    lines.sort();
 
-    Ok(amount)
+    format!("digraph bags {{\n{}\n}}\n", lines.join("\n"))
+}
+
+// The total number of bags inside the given bag. Every bag type is only counted once, and the
+// total is checked for overflows, as it grows exponentially with the depth of the rules.
+fn get_bag_count(
+    map: &HashMap<Symbol, Vec<Content>>,
+    names: &Interner,
+    bag: Symbol,
+) -> Result<u64, String> {
+    fn count(
+        map: &HashMap<Symbol, Vec<Content>>,
+        names: &Interner,
+        bag: Symbol,
+        memo: &mut HashMap<Symbol, Option<u64>>,
+    ) -> Result<u64, String> {
+        match memo.get(&bag) {
+            Some(Some(amount)) => return Ok(*amount),
+            Some(None) => {
+                return Err(format!(
+                    "{} bags end up containing themselves",
+                    names.resolve(bag)
+                ))
+            }
+            None => {}
+        }
+
+        let contents = match map.get(&bag) {
+            None => {
+                return Err(format!(
+                    "Needed to find {}, but not found in input",
+                    names.resolve(bag)
+                ));
+            }
+            Some(c) => c,
+        };
+
+        // Mark the bag as in progress, so a cycle is an error rather than a stack overflow.
+        memo.insert(bag, None);
+        let mut amount: u64 = 0;
+        for content in contents {
+            let inner = count(map, names, content.bag_type, memo)?;
+            amount = inner
+                .checked_add(1)
+                .and_then(|n| n.checked_mul(content.amount as u64))
+                .and_then(|n| n.checked_add(amount))
+                .ok_or(format!("Too many bags inside {} bags", names.resolve(bag)))?;
+        }
+        memo.insert(bag, Some(amount));
+
+        Ok(amount)
+    }
+
+    count(map, names, bag, &mut HashMap::new())
 }
 
 #[test]
 fn test_get_bag_count() {
-    let mut bags: HashMap<String, Vec<Content>> = HashMap::new();
-    bags.insert("red".to_owned(), vec![]);
+    let mut names = Interner::new();
+    let red = names.intern("red");
+    let blue = names.intern("blue");
+    let green = names.intern("green");
+    let missing = names.intern("missing");
+
+    let mut bags: HashMap<Symbol, Vec<Content>> = HashMap::new();
+    bags.insert(red, vec![]);
     bags.insert(
-        "blue".to_owned(),
+        blue,
         vec![Content {
-            bag_type: "red".to_owned(),
+            bag_type: red,
             amount: 3,
         }],
     );
     bags.insert(
-        "green".to_owned(),
+        green,
         vec![
             Content {
-                bag_type: "red".to_owned(),
+                bag_type: red,
                 amount: 2,
             },
             Content {
-                bag_type: "blue".to_owned(),
+                bag_type: blue,
                 amount: 1,
             },
         ],
     );
 
-    assert_eq!(get_bag_count(&bags, &"red".to_owned()), Ok(0));
-    assert_eq!(get_bag_count(&bags, &"blue".to_owned()), Ok(3));
-    assert_eq!(get_bag_count(&bags, &"green".to_owned()), Ok(6));
+    assert_eq!(get_bag_count(&bags, &names, red), Ok(0));
+    assert_eq!(get_bag_count(&bags, &names, blue), Ok(3));
+    assert_eq!(get_bag_count(&bags, &names, green), Ok(6));
+    assert_eq!(
+        get_bag_count(&bags, &names, missing),
+        Err("Needed to find missing, but not found in input".to_owned())
+    );
+}
+
+#[test]
+fn test_find_deepest_chain_shared() {
+    // Every bag contains both bags of the next level, so there are 2^60 paths to the bottom;
+    // searching them all would never finish.
+    let mut names = Interner::new();
+    let levels = (0..=60)
+        .map(|i| {
+            (
+                names.intern(&format!("a{}", i)),
+                names.intern(&format!("b{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut bags: HashMap<Symbol, Vec<Content>> = HashMap::new();
+    for pair in levels.windows(2) {
+        let next = || {
+            vec![
+                Content {
+                    bag_type: pair[1].0,
+                    amount: 1,
+                },
+                Content {
+                    bag_type: pair[1].1,
+                    amount: 1,
+                },
+            ]
+        };
+        bags.insert(pair[0].0, next());
+        bags.insert(pair[0].1, next());
+    }
+    bags.insert(levels[60].0, vec![]);
+    bags.insert(levels[60].1, vec![]);
+
+    let chain = find_deepest_chain(&bags, &names, levels[0].0).unwrap();
+    assert_eq!(chain.len(), 61);
+    assert_eq!(chain[0], levels[0].0);
+}
+
+#[test]
+fn test_validate_rules() {
+    let mut names = Interner::new();
+    let data = "red bags contain 1 blue bag, 0 green bags.\n\
+                blue bags contain 2 green bags, 3 white bags.\n\
+                green bags contain 1 red bag.\n\
+                blue bags contain no other bags.";
+    let rules = parse_rules(data, &mut names).unwrap();
+    let problems = validate_rules(&rules)
+        .iter()
+        .map(|p| p.describe(&names))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        problems,
+        vec![
+            "There is more than one rule for blue bags",
+            "red bags contain 0 green bags",
+            "blue bags contain white bags, but there is no rule for those",
+            "Bags containing themselves: red > green > red",
+        ]
+    );
+
+    let map: HashMap<_, _> = rules.into_iter().collect();
+    assert_eq!(
+        get_bag_count(&map, &names, names.get("red").unwrap()),
+        Err("red bags end up containing themselves".to_owned())
+    );
+    assert!(parse_rules("red bags contain -1 blue bags.", &mut names).is_err());
+
+    // 30 levels of 1000 bags each do not fit in a u64.
+    let mut names = Interner::new();
+    let data = (0..30)
+        .map(|i| format!("bag{} bags contain 1000 bag{} bags.", i, i + 1))
+        .chain(vec!["bag30 bags contain no other bags.".to_owned()])
+        .collect::<Vec<_>>()
+        .join("\n");
+    let map: HashMap<_, _> = parse_rules(&data, &mut names)
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        get_bag_count(&map, &names, names.get("bag25").unwrap()),
+        Ok(1001001001001000)
+    );
+    assert_eq!(
+        get_bag_count(&map, &names, names.get("bag0").unwrap()),
+        Err("Too many bags inside bag23 bags".to_owned())
+    );
+}
+
+#[test]
+fn test_queries() {
+    let mut names = Interner::new();
+    let rules = [
+        "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+        "dark orange bags contain 3 bright white bags, 4 muted yellow bags.",
+        "bright white bags contain 1 shiny gold bag.",
+        "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
+        "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.",
+        "dark olive bags contain 3 faded blue bags, 4 dotted black bags.",
+        "vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.",
+        "faded blue bags contain no other bags.",
+        "dotted black bags contain no other bags.",
+    ];
+    let bags = rules
+        .iter()
+        .map(|r| parse_line(r, &mut names))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .unwrap();
+    let gold = names.get("shiny gold").unwrap();
+    let olive = names.get("dark olive").unwrap();
+
+    assert_eq!(
+        sorted_names(&names, &find_containers(&bags, gold)),
+        vec!["bright white", "dark orange", "light red", "muted yellow"]
+    );
+    assert_eq!(
+        sorted_names(&names, &find_contents(&bags, gold)),
+        vec!["dark olive", "dotted black", "faded blue", "vibrant plum"]
+    );
+    assert_eq!(get_bag_count(&bags, &names, gold), Ok(32));
+
+    let chain = find_deepest_chain(&bags, &names, names.get("light red").unwrap()).unwrap();
+    assert_eq!(chain.len(), 5);
+    assert_eq!(names.resolve(chain[1]), "bright white");
+    assert_eq!(names.resolve(chain[2]), "shiny gold");
+
+    assert_eq!(
+        to_dot(&bags, &names, Some(olive)),
+        "digraph bags {\n  \
+         \"dark olive\" -> \"dotted black\" [label=4, weight=4];\n  \
+         \"dark olive\" -> \"faded blue\" [label=3, weight=3];\n  \
+         \"dotted black\";\n  \
+         \"faded blue\";\n}\n"
+    );
+    assert_eq!(to_dot(&bags, &names, None).lines().count(), 17);
 }
 
 pub fn puzzle2() {
-    let bags = match read_input_data() {
+    let (names, bags) = match read_input_data() {
         Err(e) => {
             println!("{}", e);
             return;
@@ -291,8 +781,13 @@ pub fn puzzle2() {
         Ok(v) => v,
     };
 
+    let shiny_gold = match names.get("shiny gold") {
+        None => return println!("There are no rules for shiny gold bags"),
+        Some(v) => v,
+    };
+
     // We need to figure out the amount of bags we need _inside_ our shiny gold bag. (Oh dear)
-    let result = match get_bag_count(&bags, &"shiny gold".to_owned()) {
+    let result = match get_bag_count(&bags, &names, shiny_gold) {
         Err(e) => {
             println!("{}", e);
             return;
@@ -305,3 +800,108 @@ pub fn puzzle2() {
         result
     );
 }
+
+fn find_bag(names: &Interner, options: &Options) -> Result<Option<Symbol>, String> {
+    match options.get::<String>("bag")? {
+        None => Ok(None),
+        Some(bag) => names
+            .get(&bag)
+            .map(Some)
+            .ok_or(format!("There are no rules for {} bags", bag)),
+    }
+}
+
+// Answers questions about the `--bag` colour (defaults to shiny gold): which bags can contain it,
+// which bags it has to contain, how many bags that is in total and its deepest chain of bags.
+pub fn query(options: &Options) {
+    let (names, bags) = match read_input_data() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let bag = match find_bag(&names, options).and_then(|b| {
+        b.or_else(|| names.get("shiny gold"))
+            .ok_or_else(|| "There are no rules for shiny gold bags".to_owned())
+    }) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+    let name = names.resolve(bag);
+
+    let containers = sorted_names(&names, &find_containers(&bags, bag));
+    println!(
+        "{} bag(s) can contain {}: {}",
+        containers.len(),
+        name,
+        containers.join(", ")
+    );
+
+    let contents = sorted_names(&names, &find_contents(&bags, bag));
+    println!(
+        "{} must contain {} type(s) of bag: {}",
+        name,
+        contents.len(),
+        contents.join(", ")
+    );
+
+    match get_bag_count(&bags, &names, bag) {
+        Err(e) => eprintln!("{}", e),
+        Ok(count) => println!("{} contains {} bags in total", name, count),
+    }
+
+    match find_deepest_chain(&bags, &names, bag) {
+        Err(e) => eprintln!("{}", e),
+        Ok(chain) => println!(
+            "Deepest chain: {}",
+            chain
+                .iter()
+                .map(|b| names.resolve(*b))
+                .collect::<Vec<_>>()
+                .join(" > ")
+        ),
+    }
+}
+
+// Exports the rules (or with `--bag`, the rules reachable from that bag) in Graphviz format, to
+// stdout or the `--output` file.
+pub fn export_dot(options: &Options) {
+    let (names, bags) = match read_input_data() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let (root, output) = match find_bag(&names, options)
+        .and_then(|root| options.get::<String>("output").map(|o| (root, o)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let dot = to_dot(&bags, &names, root);
+    match output {
+        None => print!("{}", dot),
+        Some(path) => match write(&path, dot) {
+            Err(e) => eprintln!("Could not write {}: {}", path, e),
+            Ok(_) => println!("Wrote the bag rules to {}", path),
+        },
+    }
+}
+
+// Lists all problems with the rules, instead of stopping at the first one.
+pub fn validate() {
+    let mut names = Interner::new();
+    let rules = match read_raw_input(7).and_then(|data| parse_rules(&data, &mut names)) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let problems = validate_rules(&rules);
+    for problem in &problems {
+        println!("{}", problem.describe(&names));
+    }
+    println!(
+        "Found {} problem(s) in {} rules",
+        problems.len(),
+        rules.len()
+    );
+}
@@ -1,8 +1,46 @@
 use crate::util::input::read_raw_input;
+use crate::util::options::Options;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::io::{stdin, stdout, BufRead, Write};
 
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Op {
+    Acc,
+    Jmp,
+    Nop,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 struct Instruction {
-    op: String,
-    val: i32,
+    op: Op,
+    arg: i32,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Acc => write!(f, "acc"),
+            Op::Jmp => write!(f, "jmp"),
+            Op::Nop => write!(f, "nop"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.op, self.arg)
+    }
+}
+
+fn parse_op(op: &str) -> Result<Op, String> {
+    match op {
+        "acc" => Ok(Op::Acc),
+        "jmp" => Ok(Op::Jmp),
+        "nop" => Ok(Op::Nop),
+        _ => Err(format!("Unknown op '{}'", op)),
+    }
 }
 
 fn parse_line(line: String) -> Result<Instruction, String> {
@@ -15,151 +53,910 @@ fn parse_line(line: String) -> Result<Instruction, String> {
         ));
     }
 
-    let op = parts[0].to_string();
-    let val = parts[1].parse::<i32>().map_err(|e| format!("{}", e))?;
+    let op = parse_op(&parts[0])?;
+    let arg = parts[1].parse::<i32>().map_err(|e| format!("{}", e))?;
 
-    Ok(Instruction { op, val })
+    Ok(Instruction { op, arg })
 }
 
 fn parse_program(program: String) -> Result<Vec<Instruction>, String> {
-    let lines: Result<Vec<_>, _> = program
+    program
         .split("\n")
-        .map(str::to_owned)
-        .map(parse_line)
-        .collect();
-    lines
+        .enumerate()
+        .map(|(i, line)| parse_line(line.to_owned()).map_err(|e| format!("Line {}: {}", i + 1, e)))
+        .collect()
 }
 
-fn find_loop(program: &Vec<Instruction>) -> i32 {
-    let mut acc = 0;
-    let mut idx = 0;
+// Why the program stopped running.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Halt {
+    // Tried to run the instruction right after the last one; the way a program should end.
+    Terminated { acc: i64 },
+    // About to run the instruction at `pc` for the second time.
+    Loop { pc: usize, acc: i64 },
+    // Jumped somewhere outside of the program (other than right after the end).
+    OutOfBounds { pc: i64, acc: i64 },
+}
 
-    let mut seen: Vec<i32> = vec![];
+struct Vm<'a> {
+    program: &'a [Instruction],
+    pc: i64,
+    acc: i64,
+    visited: Vec<bool>,
+}
+
+impl<'a> Vm<'a> {
+    fn new(program: &'a [Instruction]) -> Vm<'a> {
+        Vm {
+            program,
+            pc: 0,
+            acc: 0,
+            visited: vec![false; program.len()],
+        }
+    }
+
+    // Runs a single instruction, unless the program has to halt before it.
+    fn step(&mut self) -> Option<Halt> {
+        if self.pc == self.program.len() as i64 {
+            return Some(Halt::Terminated { acc: self.acc });
+        }
+        if self.pc < 0 || self.pc > self.program.len() as i64 {
+            return Some(Halt::OutOfBounds {
+                pc: self.pc,
+                acc: self.acc,
+            });
+        }
+
+        let pc = self.pc as usize;
+        if self.visited[pc] {
+            return Some(Halt::Loop { pc, acc: self.acc });
+        }
+        self.visited[pc] = true;
+
+        let instruction = self.program[pc];
+        match instruction.op {
+            Op::Acc => {
+                self.acc += instruction.arg as i64;
+                self.pc += 1;
+            }
+            Op::Jmp => self.pc += instruction.arg as i64,
+            Op::Nop => self.pc += 1,
+        }
+
+        None
+    }
+
+    fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn example_program() -> Vec<Instruction> {
+    parse_program(
+        "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6".to_owned(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_vm() {
+    let mut program = example_program();
+    assert_eq!(Vm::new(&program).run(), Halt::Loop { pc: 1, acc: 5 });
+
+    program[7].op = Op::Nop;
+    assert_eq!(Vm::new(&program).run(), Halt::Terminated { acc: 8 });
+
+    program[2].arg = 8;
+    assert_eq!(
+        Vm::new(&program).run(),
+        Halt::OutOfBounds { pc: 10, acc: 1 }
+    );
+
+    let mut vm = Vm::new(&program);
+    assert_eq!(vm.step(), None);
+    assert_eq!((vm.pc, vm.acc), (1, 0));
+
+    assert_eq!(
+        parse_program("nop +0\nmul +2".to_owned()).err(),
+        Some("Line 2: Unknown op 'mul'".to_owned())
+    );
+}
+
+// One executed instruction, with the accumulator after running it.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct TraceStep {
+    pc: usize,
+    instruction: Instruction,
+    acc: i64,
+}
+
+fn trace_program(program: &[Instruction]) -> (Vec<TraceStep>, Halt) {
+    let mut vm = Vm::new(program);
+    let mut steps = vec![];
 
-    // We run the program until we encounter a loop. The result is the accumulator value at that point.
     loop {
-        if seen.contains(&idx) {
-            println!("Found loop, executing {} again!", idx);
-            break;
+        let pc = vm.pc;
+        if let Some(halt) = vm.step() {
+            return (steps, halt);
+        }
+        steps.push(TraceStep {
+            pc: pc as usize,
+            instruction: program[pc as usize],
+            acc: vm.acc,
+        });
+    }
+}
+
+fn trace_to_csv(steps: &[TraceStep]) -> String {
+    let mut lines = vec!["step,pc,op,arg,acc".to_owned()];
+    for (i, step) in steps.iter().enumerate() {
+        lines.push(format!(
+            "{},{},{},{},{}",
+            i + 1,
+            step.pc,
+            step.instruction.op,
+            step.instruction.arg,
+            step.acc
+        ));
+    }
+    lines.join("\n") + "\n"
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Breakpoint {
+    Pc(usize),
+    Op(Op),
+}
+
+impl Breakpoint {
+    fn parse(value: &str) -> Result<Breakpoint, String> {
+        match value.parse::<usize>() {
+            Ok(pc) => Ok(Breakpoint::Pc(pc)),
+            Err(_) => parse_op(value)
+                .map(Breakpoint::Op)
+                .map_err(|_| format!("Expected a pc or op to break on, but got '{}'", value)),
+        }
+    }
+}
+
+// Steps through a program, one command at a time (see HELP); every command returns the lines to
+// show to the user.
+struct Debugger<'a> {
+    vm: Vm<'a>,
+    breakpoints: Vec<Breakpoint>,
+    watch: bool,
+    halted: Option<Halt>,
+}
+
+const HELP: &str = "Commands:
+  step [n]        run the next (n) instruction(s)
+  continue        run until a breakpoint is hit or the program halts
+  break <pc|op>   stop before running the instruction at pc, or any instruction with op
+  delete <pc|op>  remove a breakpoint
+  watch           toggle printing every change to the accumulator
+  print           show pc, acc and the next instruction
+  quit";
+
+impl<'a> Debugger<'a> {
+    fn new(program: &'a [Instruction]) -> Debugger<'a> {
+        Debugger {
+            vm: Vm::new(program),
+            breakpoints: vec![],
+            watch: false,
+            halted: None,
         }
+    }
 
-        seen.push(idx);
-        let instr = &program[idx as usize];
-        match &instr.op[0..3] {
-            "acc" => {
-                acc += instr.val;
-                idx += 1;
-                continue;
+    fn location(&self) -> String {
+        let next = match self.vm.program.get(self.vm.pc as usize) {
+            Some(instruction) if self.vm.pc >= 0 => instruction.to_string(),
+            _ => "-".to_owned(),
+        };
+        format!("pc {}, acc {}, next: {}", self.vm.pc, self.vm.acc, next)
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let pc = self.vm.pc;
+        let op = if pc >= 0 {
+            self.vm.program.get(pc as usize).map(|i| i.op)
+        } else {
+            None
+        };
+        self.breakpoints.iter().any(|b| match b {
+            Breakpoint::Pc(p) => *p as i64 == pc,
+            Breakpoint::Op(o) => Some(*o) == op,
+        })
+    }
+
+    // Runs one instruction; returns false once the program halted.
+    fn step(&mut self, output: &mut Vec<String>) -> bool {
+        if let Some(halt) = self.halted {
+            output.push(format!("The program has halted: {:?}", halt));
+            return false;
+        }
+
+        let acc = self.vm.acc;
+        let pc = self.vm.pc;
+        if let Some(halt) = self.vm.step() {
+            self.halted = Some(halt);
+            output.push(format!("Halted: {:?}", halt));
+            return false;
+        }
+
+        if self.watch && self.vm.acc != acc {
+            output.push(format!("acc {} -> {} at pc {}", acc, self.vm.acc, pc));
+        }
+        true
+    }
+
+    fn command(&mut self, line: &str) -> Result<Vec<String>, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let mut output = vec![];
+
+        match words[..] {
+            ["step"] | ["s"] => {
+                if self.step(&mut output) {
+                    output.push(self.location());
+                }
+            }
+            ["step", n] | ["s", n] => {
+                let n = n.parse::<usize>().map_err(|e| format!("{} ({})", e, n))?;
+                if (0..n).all(|_| self.step(&mut output)) {
+                    output.push(self.location());
+                }
+            }
+            ["continue"] | ["c"] => {
+                // Always run at least one instruction, so we can continue from a breakpoint.
+                let mut running = self.step(&mut output);
+                while running && !self.at_breakpoint() {
+                    running = self.step(&mut output);
+                }
+                if running {
+                    output.push(format!("Breakpoint: {}", self.location()));
+                }
             }
-            "jmp" => {
-                idx += instr.val;
-                continue;
+            ["break", at] | ["b", at] => {
+                let breakpoint = Breakpoint::parse(at)?;
+                if !self.breakpoints.contains(&breakpoint) {
+                    self.breakpoints.push(breakpoint);
+                }
+                output.push(format!("Breakpoints: {:?}", self.breakpoints));
             }
-            _ => {
-                idx += 1;
-                continue;
-            } // Consider anything unknown as 'NOP'
+            ["delete", at] | ["d", at] => {
+                let breakpoint = Breakpoint::parse(at)?;
+                self.breakpoints.retain(|b| *b != breakpoint);
+                output.push(format!("Breakpoints: {:?}", self.breakpoints));
+            }
+            ["watch"] | ["w"] => {
+                self.watch = !self.watch;
+                output.push(format!(
+                    "Watching acc: {}",
+                    if self.watch { "on" } else { "off" }
+                ));
+            }
+            ["print"] | ["p"] => output.push(self.location()),
+            ["help"] | ["h"] => output.push(HELP.to_owned()),
+            _ => return Err(format!("Unknown command '{}', try help", line.trim())),
+        }
+
+        Ok(output)
+    }
+}
+
+#[test]
+fn test_debugger() {
+    let program = example_program();
+    let mut debugger = Debugger::new(&program);
+    let mut run = |command: &str| debugger.command(command).unwrap().join("\n");
+
+    assert_eq!(run("print"), "pc 0, acc 0, next: nop +0");
+    assert_eq!(run("step 2"), "pc 2, acc 1, next: jmp +4");
+    assert_eq!(run("break jmp"), "Breakpoints: [Op(Jmp)]");
+    assert_eq!(run("b 3"), "Breakpoints: [Op(Jmp), Pc(3)]");
+    assert_eq!(run("continue"), "Breakpoint: pc 7, acc 2, next: jmp -4");
+    assert_eq!(run("c"), "Breakpoint: pc 3, acc 2, next: acc +3");
+    assert_eq!(run("delete jmp"), "Breakpoints: [Pc(3)]");
+    assert_eq!(run("watch"), "Watching acc: on");
+    assert_eq!(
+        run("c"),
+        "acc 2 -> 5 at pc 3\nHalted: Loop { pc: 1, acc: 5 }"
+    );
+    assert_eq!(run("s"), "The program has halted: Loop { pc: 1, acc: 5 }");
+    assert!(debugger.command("jump 3").is_err());
+    assert!(debugger.command("break xyz").is_err());
+}
+
+#[test]
+fn test_trace() {
+    let (steps, halt) = trace_program(&example_program());
+    assert_eq!(halt, Halt::Loop { pc: 1, acc: 5 });
+    assert_eq!(steps.len(), 7);
+    assert_eq!(
+        steps[5],
+        TraceStep {
+            pc: 3,
+            instruction: Instruction {
+                op: Op::Acc,
+                arg: 3
+            },
+            acc: 5
+        }
+    );
+    assert_eq!(
+        trace_to_csv(&steps[..2]),
+        "step,pc,op,arg,acc\n1,0,nop,0,0\n2,1,acc,1,1\n"
+    );
+}
+
+// Assembles a program with labels and comments into the day 8 format. A label is a name
+// followed by ':', on its own line or in front of an instruction; jmp and nop can use a label
+// instead of an offset. Everything after a '#' is a comment.
+fn assemble(source: &str) -> Result<Vec<Instruction>, String> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    // (line number, op, argument) for every instruction
+    let mut lines: Vec<(usize, &str, &str)> = vec![];
+
+    for (i, line) in source.split('\n').enumerate() {
+        let error = |e: String| format!("Line {}: {}", i + 1, e);
+        let mut line = line.split('#').next().unwrap_or_default().trim();
+
+        if let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if !is_label(label) {
+                return Err(error(format!("Invalid label '{}'", label)));
+            }
+            if labels.insert(label, lines.len()).is_some() {
+                return Err(error(format!("Label '{}' is defined twice", label)));
+            }
+            line = line[colon + 1..].trim();
         }
+        if line.is_empty() {
+            continue;
+        }
+
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        if parts.len() != 2 {
+            return Err(error(format!(
+                "Expected an op and argument, but got '{}'",
+                line
+            )));
+        }
+        lines.push((i + 1, parts[0], parts[1]));
     }
 
-    acc
+    lines
+        .iter()
+        .enumerate()
+        .map(|(pc, &(line, op, arg))| {
+            let error = |e: String| format!("Line {}: {}", line, e);
+            let op = parse_op(op).map_err(error)?;
+            let arg = if is_label(arg) {
+                match labels.get(arg) {
+                    None => return Err(error(format!("Unknown label '{}'", arg))),
+                    Some(&target) if op != Op::Acc => target as i32 - pc as i32,
+                    Some(_) => return Err(error("acc needs a number, not a label".to_owned())),
+                }
+            } else {
+                arg.parse::<i32>()
+                    .map_err(|e| error(format!("{} ({})", e, arg)))?
+            };
+            Ok(Instruction { op, arg })
+        })
+        .collect()
+}
+
+fn is_label(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The reverse of `assemble`: every jmp or nop target inside the program (or right after it) gets a
+// label, numbered in program order.
+fn disassemble(program: &[Instruction]) -> String {
+    let target = |pc: usize, instruction: &Instruction| match instruction.op {
+        Op::Acc => None,
+        Op::Jmp | Op::Nop => {
+            let target = pc as i64 + instruction.arg as i64;
+            if target >= 0 && target <= program.len() as i64 {
+                Some(target as usize)
+            } else {
+                None
+            }
+        }
+    };
+
+    let mut targets = program
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, i)| target(pc, i))
+        .collect::<Vec<_>>();
+    targets.sort_unstable();
+    targets.dedup();
+    let label = |pc: usize| format!("l{}", targets.binary_search(&pc).unwrap_or_default() + 1);
+
+    let mut lines = vec![];
+    for (pc, instruction) in program.iter().enumerate() {
+        if targets.binary_search(&pc).is_ok() {
+            lines.push(format!("{}:", label(pc)));
+        }
+        lines.push(match target(pc, instruction) {
+            Some(t) => format!("    {} {}", instruction.op, label(t)),
+            None => format!("    {}", instruction),
+        });
+    }
+    if targets.binary_search(&program.len()).is_ok() {
+        lines.push(format!("{}:", label(program.len())));
+    }
+
+    lines.join("\n")
+}
+
+// Removes instructions that can never run and folds consecutive accs into one, keeping jumps
+// pointing at the same instructions. The optimised program ends the same way with the same
+// accumulator, but a nop keeps its argument, so it can no longer be flipped into the same jmp.
+fn optimise(program: &[Instruction]) -> Vec<Instruction> {
+    let len = program.len();
+    let jump_target = |pc: usize| pc as i64 + program[pc].arg as i64;
+
+    let mut reachable = vec![false; len];
+    let mut search = if len > 0 { vec![0] } else { vec![] };
+    while let Some(pc) = search.pop() {
+        if reachable[pc] {
+            continue;
+        }
+        reachable[pc] = true;
+        if let Some(next) = successor(program, pc, program[pc].op) {
+            if next < len {
+                search.push(next);
+            }
+        }
+    }
+
+    let mut jumped_to = vec![false; len + 1];
+    for pc in (0..len).filter(|&pc| reachable[pc] && program[pc].op == Op::Jmp) {
+        let target = jump_target(pc);
+        if target >= 0 && target <= len as i64 {
+            jumped_to[target as usize] = true;
+        }
+    }
+
+    // Where every original instruction ends up; folded accs share the new pc of the first one.
+    let mut new_pc = vec![0; len + 1];
+    let mut optimised: Vec<Instruction> = vec![];
+    for pc in 0..len {
+        new_pc[pc] = optimised.len();
+        if !reachable[pc] {
+            continue;
+        }
+
+        let instruction = program[pc];
+        if let Some(last) = optimised.last_mut() {
+            let previous_is_acc = pc > 0 && reachable[pc - 1] && program[pc - 1].op == Op::Acc;
+            if instruction.op == Op::Acc && previous_is_acc && !jumped_to[pc] {
+                if let Some(arg) = last.arg.checked_add(instruction.arg) {
+                    new_pc[pc] -= 1;
+                    last.arg = arg;
+                    continue;
+                }
+            }
+        }
+        optimised.push(instruction);
+    }
+    new_pc[len] = optimised.len();
+
+    // Jumps to instructions (or the end) move along with them; jumps outside of the program stay
+    // just as far outside of it.
+    let mut old_pcs = (0..len).filter(|&pc| reachable[pc]).collect::<Vec<_>>();
+    old_pcs.dedup_by_key(|pc| new_pc[*pc]);
+    for (pc, old_pc) in old_pcs.into_iter().enumerate() {
+        if optimised[pc].op != Op::Jmp {
+            continue;
+        }
+        let target = jump_target(old_pc);
+        let new_target = if target < 0 {
+            target
+        } else if target > len as i64 {
+            target - len as i64 + optimised.len() as i64
+        } else {
+            new_pc[target as usize] as i64
+        };
+        optimised[pc].arg = (new_target - pc as i64) as i32;
+    }
+
+    optimised
+}
+
+// Whether two halts are the same, apart from where in the program they happened.
+fn same_outcome(a: Halt, b: Halt) -> bool {
+    match (a, b) {
+        (Halt::Terminated { acc: a }, Halt::Terminated { acc: b }) => a == b,
+        (Halt::Loop { acc: a, .. }, Halt::Loop { acc: b, .. }) => a == b,
+        (Halt::OutOfBounds { acc: a, .. }, Halt::OutOfBounds { acc: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_assembler() {
+    let source = "
+        # Adds up to 3, then stops
+        start: acc +1
+        loop:
+            acc +1     # counting
+            nop end
+            jmp done
+        skipped: jmp loop
+        done:
+            acc +1
+        end:";
+    let program = assemble(source).unwrap();
+    assert_eq!(
+        program.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+        vec!["acc +1", "acc +1", "nop +4", "jmp +2", "jmp -3", "acc +1"]
+    );
+    assert_eq!(
+        disassemble(&program),
+        "    acc +1\nl1:\n    acc +1\n    nop l3\n    jmp l2\n    jmp l1\nl2:\n    acc +1\nl3:"
+    );
+    assert_eq!(assemble(&disassemble(&program)), Ok(program.clone()));
+    assert_eq!(
+        assemble(&disassemble(&example_program())),
+        Ok(example_program())
+    );
+
+    assert_eq!(
+        assemble("a: nop +0\na: nop +0"),
+        Err("Line 2: Label 'a' is defined twice".to_owned())
+    );
+    assert_eq!(
+        assemble("jmp nowhere"),
+        Err("Line 1: Unknown label 'nowhere'".to_owned())
+    );
+    assert!(assemble("a: acc a").is_err());
+    assert!(assemble("1a: nop +0").is_err());
+
+    let optimised = optimise(&program);
+    assert_eq!(
+        optimised.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+        vec!["acc +2", "nop +4", "jmp +1", "acc +1"]
+    );
+    assert_eq!(Vm::new(&optimised).run(), Halt::Terminated { acc: 3 });
+}
+
+#[test]
+fn test_optimise_fuzz() {
+    use crate::util::fuzz::check;
+
+    check(
+        "day 8 optimised programs end the same way",
+        |rng| {
+            let len = rng.range(1, 16);
+            (0..len)
+                .map(|_| Instruction {
+                    op: *rng.pick(&[Op::Acc, Op::Acc, Op::Jmp, Op::Nop]),
+                    arg: rng.range(-len - 2, len + 2) as i32,
+                })
+                .collect::<Vec<_>>()
+        },
+        |program| {
+            let optimised = optimise(program);
+            let (before, after) = (Vm::new(program).run(), Vm::new(&optimised).run());
+            if !same_outcome(before, after) || optimised.len() > program.len() {
+                return Err(format!("{:?} became {:?}: {:?}", before, after, optimised));
+            }
+            assert_eq!(assemble(&disassemble(program)).as_ref(), Ok(program));
+            Ok(())
+        },
+    );
+}
+
+fn find_loop(program: &[Instruction]) -> Result<i64, String> {
+    // We run the program until we encounter a loop. The result is the accumulator value at that point.
+    match Vm::new(program).run() {
+        Halt::Loop { pc, acc } => {
+            println!("Found loop, executing {} again!", pc);
+            Ok(acc)
+        }
+        halt => Err(format!("Expected the program to loop, but got {:?}", halt)),
+    }
 }
 
 pub fn puzzle1() {
     match read_raw_input(8)
         .and_then(parse_program)
-        .map(|p| find_loop(&p))
+        .and_then(|p| find_loop(&p))
     {
         Err(e) => eprintln!("{}", e),
         Ok(result) => println!("Puzzle 1 result = {}", result),
     }
 }
 
-fn try_run_program(program: Vec<&Instruction>) -> Result<i32, ()> {
-    let mut acc = 0;
-    let mut idx = 0;
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Repair {
+    pc: usize,
+    from: Op,
+    to: Op,
+    acc: i64,
+}
 
-    let mut seen: Vec<i32> = vec![];
+fn flip(op: Op) -> Option<Op> {
+    match op {
+        Op::Acc => None,
+        Op::Jmp => Some(Op::Nop),
+        Op::Nop => Some(Op::Jmp),
+    }
+}
 
-    // We run the program until we encounter a loop. The result is the accumulator value at that point.
-    loop {
-        if seen.contains(&idx) {
-            return Err(());
+// Where the instruction at `pc` continues, if that is still inside the program (or right after it).
+fn successor(program: &[Instruction], pc: usize, op: Op) -> Option<usize> {
+    let next = match op {
+        Op::Jmp => pc as i64 + program[pc].arg as i64,
+        Op::Acc | Op::Nop => pc as i64 + 1,
+    };
+    if next >= 0 && next <= program.len() as i64 {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
+
+// We should be able to fix this program by changing either a 'jmp' to 'nop' or a 'nop' to 'jmp'.
+// Instead of trying every change, we look at the control flow graph: walking it backwards from the
+// end of the program tells us which instructions end up terminating. Only changes on the path the
+// program takes now make any difference, so we follow that path until a flipped instruction
+// would continue at one of those.
+fn repair_program(program: &[Instruction]) -> Result<Repair, String> {
+    let len = program.len();
+
+    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; len + 1];
+    for pc in 0..len {
+        if let Some(next) = successor(program, pc, program[pc].op) {
+            predecessors[next].push(pc);
         }
+    }
 
-        if (idx as usize) >= program.len() {
-            // Successful execution
-            return Ok(acc);
+    let mut terminates = vec![false; len + 1];
+    terminates[len] = true;
+    let mut search = vec![len];
+    while let Some(pc) = search.pop() {
+        for &previous in &predecessors[pc] {
+            if !terminates[previous] {
+                terminates[previous] = true;
+                search.push(previous);
+            }
         }
+    }
 
-        seen.push(idx);
-        let instr = &program[idx as usize];
-        match &instr.op[0..3] {
-            "acc" => {
-                acc += instr.val;
-                idx += 1;
-                continue;
+    let mut vm = Vm::new(program);
+    loop {
+        let pc = vm.pc as usize;
+        match vm.step() {
+            None => {}
+            Some(Halt::Terminated { .. }) => {
+                return Err("The program already terminates, it does not need fixing".to_owned())
+            }
+            Some(_) => {
+                return Err("Could not find a running program by changing one op...".to_owned())
             }
-            "jmp" => {
-                idx += instr.val;
-                continue;
+        }
+
+        let from = program[pc].op;
+        let to = match flip(from) {
+            None => continue,
+            Some(op) => op,
+        };
+        match successor(program, pc, to) {
+            Some(next) if terminates[next] => {
+                let mut fixed = program.to_vec();
+                fixed[pc].op = to;
+                return match Vm::new(&fixed).run() {
+                    Halt::Terminated { acc } => Ok(Repair { pc, from, to, acc }),
+                    halt => Err(format!(
+                        "Changing {} did not fix the program: {:?}",
+                        pc, halt
+                    )),
+                };
             }
-            _ => {
-                idx += 1;
-                continue;
-            } // Consider anything unknown as 'NOP'
+            _ => continue,
         }
     }
 }
 
-fn try_fix_program(program: Vec<Instruction>) -> Result<i32, String> {
+#[test]
+fn test_repair_program() {
+    use crate::util::fuzz::check;
+
+    assert_eq!(
+        repair_program(&example_program()),
+        Ok(Repair {
+            pc: 7,
+            from: Op::Jmp,
+            to: Op::Nop,
+            acc: 8
+        })
+    );
+    assert_eq!(try_fix_program(example_program()), vec![(7, 8)]);
+
+    check(
+        "day 8 repair finds a fix the brute force finds",
+        |rng| {
+            let len = rng.range(1, 12);
+            (0..len)
+                .map(|_| Instruction {
+                    op: *rng.pick(&[Op::Acc, Op::Jmp, Op::Nop]),
+                    arg: rng.range(-len, len) as i32,
+                })
+                .collect::<Vec<_>>()
+        },
+        |program| {
+            let fixes = try_fix_program(program.clone());
+            let unfixed = Vm::new(program).run();
+            match repair_program(program) {
+                // Brute force also "fixes" programs that terminate already, by flipping an
+                // instruction that is never reached.
+                Err(_) if matches!(unfixed, Halt::Terminated { .. }) => Ok(()),
+                Ok(r) if fixes.contains(&(r.pc, r.acc)) => Ok(()),
+                Err(_) if fixes.is_empty() => Ok(()),
+                result => Err(format!(
+                    "Got {:?}, but brute force found {:?}",
+                    result, fixes
+                )),
+            }
+        },
+    );
+}
+
+// The brute force repair we started with: flip every jmp/nop and run the program again, which takes
+// quadratic time. Kept to check `repair_program` against; returns every fix that works.
+#[cfg(test)]
+fn try_fix_program(mut program: Vec<Instruction>) -> Vec<(usize, i64)> {
+    let mut fixes = vec![];
     // We should be able to fix this program by changing either a 'jmp' to 'nop' or a 'nop' to 'jmp'
     // With just one of such a change, we should be able to run the program to end successfully.
     // Given the simpleness of this, we'll just try changing them one by one, and seeing if the program
     // runs successfully or loops.
 
-    // Since we know we need to change one (puzzle 1 shows it loops), we'll replace them one by one
-    // until we have a success:
     for i in 0..program.len() {
-        if program[i].op.eq("acc") {
+        let original = program[i].op;
+        program[i].op = match original {
             // Only a JMP or NOP is wrong, so skip the ACC statements.
-            continue;
-        }
-
-        let new_instruction = if program[i].op.eq("jmp") {
-            "nop".to_owned()
-        } else {
-            "jmp".to_owned()
+            Op::Acc => continue,
+            Op::Jmp => Op::Nop,
+            Op::Nop => Op::Jmp,
         };
 
-        let new_instruction = vec![Instruction {
-            op: new_instruction,
-            val: program[i].val,
-        }];
-
-        let new_program: Vec<_> = program[0..i]
-            .iter()
-            .chain(new_instruction.iter())
-            .chain(program[i + 1..program.len()].iter())
-            .collect();
+        let halt = Vm::new(&program).run();
+        program[i].op = original;
 
-        match try_run_program(new_program) {
-            Ok(v) => return Ok(v),
-            Err(_) => continue,
+        if let Halt::Terminated { acc } = halt {
+            fixes.push((i, acc));
         }
     }
 
-    Err(format!(
-        "Could not find a running program by changing one op..."
-    ))
+    fixes
 }
 
 pub fn puzzle2() {
     match read_raw_input(8)
         .and_then(parse_program)
-        .and_then(try_fix_program)
+        .and_then(|p| repair_program(&p))
+    {
+        Err(e) => eprintln!("{}", e),
+        Ok(repair) => {
+            println!(
+                "Changed {:?} at {} to {:?}",
+                repair.from, repair.pc, repair.to
+            );
+            println!("Puzzle 2 result = {}", repair.acc);
+        }
+    }
+}
+
+// Writes every executed instruction as CSV, to stdout or the `--output` file.
+pub fn trace(options: &Options) {
+    let output = match options.get::<String>("output") {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+    let program = match read_raw_input(8).and_then(parse_program) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let (steps, halt) = trace_program(&program);
+    let csv = trace_to_csv(&steps);
+    match output {
+        None => print!("{}", csv),
+        Some(path) => {
+            if let Err(e) = write(&path, csv) {
+                return eprintln!("Could not write {}: {}", path, e);
+            }
+            println!("Wrote {} steps to {}", steps.len(), path);
+        }
+    }
+    eprintln!("Halted: {:?}", halt);
+}
+
+// An interactive debugger for the boot program, reading commands from stdin.
+pub fn debug() {
+    let program = match read_raw_input(8).and_then(parse_program) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let mut debugger = Debugger::new(&program);
+    println!("{}", HELP);
+    println!("{}", debugger.location());
+
+    let input = stdin();
+    let mut lines = input.lock().lines();
+    loop {
+        print!("> ");
+        if let Err(e) = stdout().flush() {
+            return eprintln!("{}", e);
+        }
+
+        let line = match lines.next() {
+            None => return,
+            Some(Err(e)) => return eprintln!("{}", e),
+            Some(Ok(line)) => line,
+        };
+        match line.trim() {
+            "" => continue,
+            "quit" | "q" => return,
+            command => match debugger.command(command) {
+                Err(e) => eprintln!("{}", e),
+                Ok(output) => output.iter().for_each(|l| println!("{}", l)),
+            },
+        }
+    }
+}
+
+// Assembles the `--assemble` file, and prints it in the day 8 format.
+pub fn assemble_file(options: &Options) {
+    match options
+        .get::<String>("assemble")
+        .map(|f| f.unwrap_or_default())
+        .and_then(|f| read_to_string(&f).map_err(|e| format!("Could not read {}: {}", f, e)))
+        .and_then(|source| assemble(&source))
     {
         Err(e) => eprintln!("{}", e),
-        Ok(result) => println!("Puzzle 2 result = {}", result),
+        Ok(program) => program.iter().for_each(|i| println!("{}", i)),
     }
 }
+
+// Prints the boot program with labels instead of offsets; or with `--optimise`, prints the
+// optimised program after checking it ends the same way as the original.
+pub fn disassemble_input(options: &Options) {
+    let program = match read_raw_input(8).and_then(parse_program) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    if !options.has("optimise") {
+        return println!("{}", disassemble(&program));
+    }
+
+    let optimised = optimise(&program);
+    let (before, after) = (Vm::new(&program).run(), Vm::new(&optimised).run());
+    if !same_outcome(before, after) {
+        return eprintln!(
+            "The optimised program does not end the same way: {:?} instead of {:?}",
+            after, before
+        );
+    }
+
+    println!("{}", disassemble(&optimised));
+    eprintln!(
+        "Optimised {} instructions to {}, both ending with {:?}",
+        program.len(),
+        optimised.len(),
+        after
+    );
+}
@@ -1,4 +1,6 @@
 use crate::util::input::read_raw_input;
+use crate::util::options::Options;
+use std::collections::{HashMap, VecDeque};
 
 fn parse_data(data: String) -> Result<Vec<usize>, String> {
     data.split("\n")
@@ -6,28 +8,105 @@ fn parse_data(data: String) -> Result<Vec<usize>, String> {
         .collect()
 }
 
-fn has_sum(list: &[usize], sum: usize) -> bool {
-    if list.len() == 0 {
-        return false;
+// A number is valid when it is the sum of two different numbers among the `preamble` numbers
+// before it. The validator keeps those numbers, and every sum of two of them, so checking (and
+// moving the window along) takes O(preamble) per number, instead of comparing every pair again.
+struct Validator {
+    preamble: usize,
+    index: usize,
+    window: VecDeque<usize>,
+    // How many pairs in the window add up to a sum
+    sums: HashMap<usize, usize>,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Invalid {
+    index: usize,
+    value: usize,
+}
+
+impl Validator {
+    fn new(preamble: usize) -> Result<Validator, String> {
+        if preamble < 2 {
+            return Err(format!(
+                "The preamble needs at least two numbers to sum, but got {}",
+                preamble
+            ));
+        }
+
+        Ok(Validator {
+            preamble,
+            index: 0,
+            window: VecDeque::with_capacity(preamble + 1),
+            sums: HashMap::new(),
+        })
     }
 
-    let first = list[0];
-    let rest = &list[1..];
+    // Checks the next number (unless it's part of the preamble), and adds it to the window.
+    fn push(&mut self, value: usize) -> Option<Invalid> {
+        let index = self.index;
+        self.index += 1;
 
-    rest.iter().any(|i| i + first == sum) || has_sum(rest, sum)
-}
+        let valid = self.window.len() < self.preamble
+            || self.sums.get(&value).map(|&n| n > 0).unwrap_or(false);
 
-fn find_first_invalid_number(list: &Vec<usize>, preamble: usize) -> Option<usize> {
-    // The first 25 numbers are preamble, any next number is valid iff there exist two numbers in the
-    // last 25 numbers that sum to it.
+        for &other in &self.window {
+            if let Some(sum) = pair_sum(value, other) {
+                *self.sums.entry(sum).or_insert(0) += 1;
+            }
+        }
+        self.window.push_back(value);
+
+        if self.window.len() > self.preamble {
+            if let Some(old) = self.window.pop_front() {
+                for &other in &self.window {
+                    if let Some(sum) = pair_sum(old, other) {
+                        if let Some(count) = self.sums.get_mut(&sum) {
+                            *count -= 1;
+                            if *count == 0 {
+                                self.sums.remove(&sum);
+                            }
+                        }
+                    }
+                }
+            }
+        }
 
-    for i in preamble + 1..list.len() {
-        if !has_sum(&list[i - preamble - 1..i], list[i]) {
-            return Some(list[i]);
+        if valid {
+            None
+        } else {
+            Some(Invalid { index, value })
         }
     }
+}
+
+// The two numbers need to be different; a sum too big to store can't match any number either.
+fn pair_sum(a: usize, b: usize) -> Option<usize> {
+    if a == b {
+        None
+    } else {
+        a.checked_add(b)
+    }
+}
 
-    None
+fn find_invalid_numbers<I: IntoIterator<Item = usize>>(
+    numbers: I,
+    preamble: usize,
+) -> Result<Vec<Invalid>, String> {
+    let mut validator = Validator::new(preamble)?;
+    Ok(numbers
+        .into_iter()
+        .filter_map(|n| validator.push(n))
+        .collect())
+}
+
+fn find_first_invalid_number(list: &[usize], preamble: usize) -> Option<usize> {
+    // The first 25 numbers are preamble, any next number is valid iff there exist two numbers in the
+    // last 25 numbers that sum to it.
+    let mut validator = Validator::new(preamble).ok()?;
+    list.iter()
+        .find_map(|&n| validator.push(n))
+        .map(|invalid| invalid.value)
 }
 
 #[test]
@@ -45,6 +124,37 @@ fn test_find_first_invalid_number() {
     assert_eq!(find_first_invalid_number(&fail, 25), None);
 }
 
+#[test]
+fn test_validator() {
+    let list = vec![
+        35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309, 576,
+    ];
+    assert_eq!(
+        find_invalid_numbers(list.iter().copied(), 5),
+        Ok(vec![Invalid {
+            index: 14,
+            value: 127
+        }])
+    );
+    // With a shorter preamble, more numbers are invalid; here, all of them.
+    assert_eq!(
+        find_invalid_numbers(list.iter().copied(), 2).map(|i| i.len()),
+        Ok(18)
+    );
+
+    // The last 40 would be 20 + 20, but the two numbers need to be different (and 10 + 30 are no
+    // longer in the window).
+    assert_eq!(
+        find_invalid_numbers(vec![10, 30, 40, 20, 20, 40], 2)
+            .unwrap()
+            .iter()
+            .map(|i| i.index)
+            .collect::<Vec<_>>(),
+        vec![3, 4, 5]
+    );
+    assert!(Validator::new(1).is_err());
+}
+
 pub fn puzzle1() {
     let list = match read_raw_input(9).and_then(parse_data) {
         Ok(v) => v,
@@ -61,34 +171,91 @@ pub fn puzzle1() {
     }
 }
 
-fn find_contiguous_set(list: &[usize], sum: usize) -> Option<&[usize]> {
-    let mut lower = 0;
-    let mut upper = 1;
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Range {
+    // list[start..end] sums up to the target
+    start: usize,
+    end: usize,
+    min: usize,
+    max: usize,
+}
 
-    loop {
-        let current: usize = list[lower..upper].iter().sum();
-        if current == sum {
-            return Some(&list[lower..upper]);
-        }
+impl Range {
+    // The encryption weakness: the smallest and largest number in the range added together.
+    fn weakness(&self) -> usize {
+        self.min + self.max
+    }
+}
 
-        if current < sum {
-            // Increase upper search bound
-            upper += 1;
-        } else {
-            // If we're a bigger number now, we reset to the next lower bound
-            lower += 1;
-            upper = lower + 1;
-        }
+// Finds every range of at least `min_len` contiguous numbers that sum to `target`, ordered by
+// where they start. With prefix sums, list[i..j] sums to the target when prefix[j] - prefix[i]
+// does; so we only need to look up which earlier prefixes are `target` less than the current one.
+fn find_contiguous_ranges(list: &[usize], target: usize, min_len: usize) -> Vec<Range> {
+    let mut starts: HashMap<u128, Vec<usize>> = HashMap::new();
+    let mut prefix: u128 = 0;
+    let mut ranges = vec![];
 
-        if upper >= list.len() {
-            lower += 1;
-            upper = lower + 1;
-        }
+    for end in 1..=list.len() {
+        starts.entry(prefix).or_default().push(end - 1);
+        prefix += list[end - 1] as u128;
 
-        if lower + 1 >= list.len() {
-            return None;
+        let wanted = match prefix.checked_sub(target as u128) {
+            None => continue,
+            Some(v) => v,
+        };
+        for &start in starts.get(&wanted).into_iter().flatten() {
+            if end - start < min_len.max(1) {
+                continue;
+            }
+            let numbers = &list[start..end];
+            ranges.push(Range {
+                start,
+                end,
+                min: *numbers.iter().min().unwrap_or(&0),
+                max: *numbers.iter().max().unwrap_or(&0),
+            });
         }
     }
+
+    ranges.sort_by_key(|r| (r.start, r.end));
+    ranges
+}
+
+#[test]
+fn test_find_contiguous_ranges() {
+    let list = vec![
+        35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309, 576,
+    ];
+    let ranges = find_contiguous_ranges(&list, 127, 2);
+    assert_eq!(
+        ranges,
+        vec![Range {
+            start: 2,
+            end: 6,
+            min: 15,
+            max: 47
+        }]
+    );
+    assert_eq!(ranges[0].weakness(), 62);
+
+    // The number itself is a range of one.
+    assert_eq!(find_contiguous_ranges(&list, 127, 1).len(), 2);
+    assert_eq!(find_contiguous_ranges(&list, 127, 5), vec![]);
+
+    // Zeroes make for overlapping ranges with the same sum.
+    let ranges = find_contiguous_ranges(&[1, 2, 0, 0, 3], 3, 2);
+    assert_eq!(
+        ranges.iter().map(|r| (r.start, r.end)).collect::<Vec<_>>(),
+        vec![(0, 2), (0, 3), (0, 4), (2, 5), (3, 5)]
+    );
+    assert_eq!(
+        ranges
+            .iter()
+            .map(|r| (r.start, r.end))
+            .filter(|&(s, e)| e - s >= 3)
+            .count(),
+        3
+    );
 }
 
 pub fn puzzle2() {
@@ -100,13 +267,16 @@ pub fn puzzle2() {
         }
     };
 
-    // We need to find a contiguous set of numbers that sum to the answer of puzzle 1: 26796446
-    let result = find_contiguous_set(&list, 26796446);
+    // We need to find a contiguous set of (at least two) numbers that sum to the answer of puzzle 1
+    let target = match find_first_invalid_number(&list, 25) {
+        None => return println!("Puzzle 2: Did not find an invalid number to look for"),
+        Some(v) => v,
+    };
 
-    match result {
+    match find_contiguous_ranges(&list, target, 2).first() {
         None => println!("Could not seem to find a contiguous set..."),
-        Some(v) => {
-            // Result will be the sum of the first and last number:
+        Some(range) => {
+            let v = &list[range.start..range.end];
             println!(
                 "Found a result: {} => {}",
                 v.iter()
@@ -116,12 +286,69 @@ pub fn puzzle2() {
                 v.iter().sum::<usize>()
             );
 
-            let min = v.iter().min();
-            let max = v.iter().max();
-            match (min, max) {
-                (Some(v1), Some(v2)) => println!("Puzzle 2 result: {}", v1 + v2),
-                _ => println!("Set has no first/last entry?!"),
+            // Result will be the sum of the smallest and largest number:
+            println!("Puzzle 2 result: {}", range.weakness());
+        }
+    }
+}
+
+// Lists every invalid number in the input, using a preamble of `--preamble` (25) numbers.
+pub fn validate(options: &Options) {
+    let preamble = match options.get_or("preamble", 25) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    match read_raw_input(9)
+        .and_then(parse_data)
+        .and_then(|list| find_invalid_numbers(list, preamble))
+    {
+        Err(e) => eprintln!("{}", e),
+        Ok(invalid) => {
+            for i in &invalid {
+                println!("Number {} at index {} is invalid", i.value, i.index);
             }
+            println!("Found {} invalid number(s)", invalid.len());
         }
     }
 }
+
+// Lists every range of at least `--min-len` (2) numbers summing to `--target` (by default the
+// first invalid number, with a preamble of `--preamble` (25) numbers), with its weakness.
+pub fn weaknesses(options: &Options) {
+    if options.has("target") && options.has("preamble") {
+        return eprintln!("--preamble is only used without --target, to find the target");
+    }
+
+    let list = match read_raw_input(9).and_then(parse_data) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let (target, min_len) = match options
+        .get::<usize>("target")
+        .and_then(|target| match target {
+            Some(t) => Ok(t),
+            None => find_first_invalid_number(&list, options.get_or("preamble", 25)?)
+                .ok_or_else(|| "Did not find an invalid number to look for".to_owned()),
+        })
+        .and_then(|t| options.get_or("min-len", 2).map(|m| (t, m)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let ranges = find_contiguous_ranges(&list, target, min_len);
+    for range in &ranges {
+        println!(
+            "{}..{} ({} numbers): weakness {} + {} = {}",
+            range.start,
+            range.end,
+            range.end - range.start,
+            range.min,
+            range.max,
+            range.weakness()
+        );
+    }
+    println!("Found {} range(s) summing to {}", ranges.len(), target);
+}
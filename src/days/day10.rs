@@ -1,8 +1,211 @@
+use crate::util::bignum::BigUint;
 use crate::util::input;
-use std::collections::HashMap;
+use crate::util::options::Options;
+use crate::util::random::Rng;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The joltages in a chain of adapters: the outlet (0), every adapter in order, and the device
+// (`device_offset` above the highest adapter). An adapter can take an input of up to `max_gap`
+// lower than its own joltage.
+struct Chain {
+    joltages: Vec<u64>,
+    max_gap: u64,
+}
+
+impl Chain {
+    fn new(adapters: &[i128], max_gap: u64, device_offset: u64) -> Result<Chain, String> {
+        if max_gap == 0 {
+            return Err("The maximum gap needs to be at least 1 jolt".to_owned());
+        }
+
+        let mut joltages = adapters
+            .iter()
+            .map(|&a| {
+                if a > 0 && a <= u64::MAX as i128 {
+                    Ok(a as u64)
+                } else {
+                    Err(format!("Invalid adapter joltage {}", a))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        joltages.push(0);
+        joltages.sort_unstable();
+
+        let device = joltages[joltages.len() - 1]
+            .checked_add(device_offset)
+            .ok_or("The device joltage does not fit in a u64")?;
+        joltages.push(device);
+
+        Ok(Chain { joltages, max_gap })
+    }
+
+    // How often every difference occurs when using all adapters; or an error if they can't all be
+    // used together.
+    fn differences(&self) -> Result<BTreeMap<u64, usize>, String> {
+        let mut histogram = BTreeMap::new();
+        for pair in self.joltages.windows(2) {
+            let difference = pair[1] - pair[0];
+            if difference > self.max_gap {
+                return Err(format!(
+                    "Can not connect {} to {}, the difference is more than {} jolts",
+                    pair[0], pair[1], self.max_gap
+                ));
+            }
+            *histogram.entry(difference).or_insert(0) += 1;
+        }
+        Ok(histogram)
+    }
+
+    // For every joltage, the number of ways it can be connected to the device.
+    fn ways_to_device(&self) -> Vec<BigUint> {
+        let len = self.joltages.len();
+        let mut ways = vec![BigUint::zero(); len];
+        ways[len - 1] = BigUint::from(1);
+
+        for i in (0..len - 1).rev() {
+            let mut options = BigUint::zero();
+            // Get the following adapters (or device) we can still connect to:
+            for (joltage, next) in self.joltages[i + 1..].iter().zip(&ways[i + 1..]) {
+                if joltage - self.joltages[i] > self.max_gap {
+                    break;
+                }
+                options = options.add(next);
+            }
+            ways[i] = options;
+        }
+
+        ways
+    }
+
+    fn arrangements(&self) -> BigUint {
+        self.ways_to_device().swap_remove(0)
+    }
+
+    // The adapters that are part of every arrangement. Since the joltages are sorted, an adapter can
+    // be left out exactly when the adapters right before and after it can connect to each other;
+    // any chain using it could use those neighbours instead.
+    fn mandatory(&self) -> Result<Vec<u64>, String> {
+        if self.arrangements().is_zero() {
+            return Err("There is no way to connect the device at all".to_owned());
+        }
+
+        Ok((1..self.joltages.len() - 1)
+            .filter(|&i| self.joltages[i + 1] - self.joltages[i - 1] > self.max_gap)
+            .map(|i| self.joltages[i])
+            .collect())
+    }
+
+    // Every arrangement, from the outlet to the device, in lexicographic order. Lazy, so it can be
+    // used even when there are far too many to list.
+    fn iter_arrangements(&self) -> Arrangements<'_> {
+        Arrangements {
+            chain: self,
+            ways: self.ways_to_device(),
+            path: vec![],
+            started: false,
+        }
+    }
+
+    // The k-th (from 0) arrangement in lexicographic order, without listing the ones before it:
+    // at every step we skip over all arrangements going through a lower next adapter at once.
+    fn nth_arrangement(&self, k: &BigUint) -> Option<Vec<u64>> {
+        let ways = self.ways_to_device();
+        let mut k = k.clone();
+        if k >= ways[0] {
+            return None;
+        }
+
+        let mut path = vec![0];
+        let mut i = 0;
+        while i < self.joltages.len() - 1 {
+            let mut next = self.successors(i, i, &ways);
+            i = loop {
+                let s = next.next()?;
+                match k.checked_sub(&ways[s]) {
+                    None => break s,
+                    Some(rest) => k = rest,
+                }
+            };
+            path.push(i);
+        }
+
+        Some(path.iter().map(|&i| self.joltages[i]).collect())
+    }
+
+    // A uniformly random arrangement, or None if there aren't any.
+    fn random_arrangement(&self, rng: &mut Rng) -> Option<Vec<u64>> {
+        let total = self.arrangements();
+        if total.is_zero() {
+            return None;
+        }
+        self.nth_arrangement(&total.random_below(rng))
+    }
+
+    // The indexes after `after` that `i` can connect to, and that lead to the device.
+    fn successors<'a>(
+        &'a self,
+        i: usize,
+        after: usize,
+        ways: &'a [BigUint],
+    ) -> impl Iterator<Item = usize> + 'a {
+        (after + 1..self.joltages.len())
+            .take_while(move |&s| self.joltages[s] - self.joltages[i] <= self.max_gap)
+            .filter(move |&s| !ways[s].is_zero())
+    }
+}
+
+// A depth first search through the chain, which only ever goes to adapters that still lead to the
+// device; so every step down the path ends in an arrangement.
+struct Arrangements<'a> {
+    chain: &'a Chain,
+    ways: Vec<BigUint>,
+    // Indexes into the chain's joltages
+    path: Vec<usize>,
+    started: bool,
+}
+
+impl Arrangements<'_> {
+    // Follows the lowest adapters from the end of the path to the device.
+    fn descend(&mut self) -> Vec<u64> {
+        let device = self.chain.joltages.len() - 1;
+        while let Some(&i) = self.path.last().filter(|&&i| i != device) {
+            let next = self.chain.successors(i, i, &self.ways).next();
+            self.path
+                .push(next.expect("Every adapter on the path leads to the device"));
+        }
+        self.path.iter().map(|&i| self.chain.joltages[i]).collect()
+    }
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Vec<u64>> {
+        if !self.started {
+            self.started = true;
+            if self.ways[0].is_zero() {
+                return None;
+            }
+            self.path.push(0);
+            return Some(self.descend());
+        }
+
+        // Backtrack to the last adapter that has a higher option we haven't taken yet.
+        while let Some(taken) = self.path.pop() {
+            let i = *self.path.last()?;
+            let next = self.chain.successors(i, taken, &self.ways).next();
+            if let Some(next) = next {
+                self.path.push(next);
+                return Some(self.descend());
+            }
+        }
+        None
+    }
+}
 
 pub fn puzzle1() {
-    let adapters = match input::read_numeric_input(10) {
+    let chain = match input::read_numeric_input(10).and_then(|a| Chain::new(&a, 3, 3)) {
         Err(e) => {
             eprintln!("{}", e);
             return;
@@ -14,23 +217,16 @@ pub fn puzzle1() {
     // The sockets starts at 0, and each adapter can jump 1, 2, or 3 up from the previous value.
     // Finally, after the highest adapter, there is a jump of 3 to the device.
     // The puzzle result is the amount of increases by 1 multiplied by those of 3.
-
-    let mut sorted = adapters.clone();
-    sorted.sort(); // we only have an in-place sorting function.
-
-    let mut hops_of_one = 0;
-    let mut hops_of_three = 1; // Init with one, as we know there is always a hop of three at the end
-
-    for i in 0..sorted.len() {
-        let previous = if i == 0 { 0 } else { sorted[i - 1] };
-        let current = sorted[i];
-
-        match current - previous {
-            1 => hops_of_one += 1,
-            3 => hops_of_three += 1,
-            _ => {}
+    let differences = match chain.differences() {
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
-    }
+        Ok(v) => v,
+    };
+
+    let hops_of_one = *differences.get(&1).unwrap_or(&0);
+    let hops_of_three = *differences.get(&3).unwrap_or(&0);
 
     println!(
         "Puzzle 1: {} hops of one, {} hops of three. Result = {}",
@@ -53,7 +249,13 @@ pub fn puzzle2() {
     // our device (highest value + 3).
     // There is a lot of inputs, so we need to be smart about this.
 
-    let result = get_possible_connection_count(&adapters);
+    let result = match get_possible_connection_count(&adapters) {
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+        Ok(v) => v,
+    };
 
     println!(
         "Puzzle 2: Got {} possible ways to connect the adapters!",
@@ -145,71 +347,231 @@ pub fn puzzle2() {
 // 0 = 1 * 3 to 2, 1 * 2 to 3 = 5
 // Hallelujah!
 
-fn get_possible_connection_count(adapters: &Vec<i128>) -> i128 {
-    // first, sort this thing...
-    let mut sorted = adapters.clone();
-    sorted.sort();
-    sorted.reverse();
+// What we do in `Chain::ways_to_device`; we create a mapping from the last number till the first,
+// calculating the amount of possibilities. The device gets '1', the last adapter also 1, as there
+// is nothing to skip. From there on, we add all calculated values together from the numbers we can
+// reach. The outlet (0) is part of the chain, so its value is the result.
+fn get_possible_connection_count(adapters: &[i128]) -> Result<BigUint, String> {
+    Chain::new(adapters, 3, 3).map(|c| c.arrangements())
+}
 
-    // What we'll do; we create a mapping from the last number till the first, calculating
-    // the amount of possibilities. The last one gets '1' (to the device adapter), the one before
-    // that is also 1, as there is nothing to skip. From there on, we add all calculated values
-    // together from the numbers we can reach.
+#[test]
+fn test_get_possible_connection_count() {
+    assert_eq!(
+        get_possible_connection_count(&[16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4]),
+        Ok(BigUint::from(8))
+    );
+    assert_eq!(
+        get_possible_connection_count(&[
+            28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
+            8, 17, 7, 9, 4, 2, 34, 10, 3
+        ]),
+        Ok(BigUint::from(19208))
+    );
+}
 
-    let mut value_map: HashMap<i128, i128> = HashMap::with_capacity(sorted.len());
+#[test]
+fn test_chain() {
+    let adapters = [16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+    let chain = Chain::new(&adapters, 3, 3).unwrap();
+    assert_eq!(
+        chain.differences(),
+        Ok(vec![(1, 7), (3, 5)].into_iter().collect())
+    );
+    assert_eq!(chain.mandatory(), Ok(vec![1, 4, 7, 10, 12, 15, 16, 19]));
 
-    for i in 0..sorted.len() {
-        let current = sorted[i];
+    // With bigger gaps, more arrangements are possible, and no adapter is needed in all of them.
+    let chain = Chain::new(&adapters, 4, 1).unwrap();
+    assert_eq!(chain.arrangements(), BigUint::from(275));
+    assert_eq!(chain.mandatory(), Ok(vec![]));
 
-        if i == 0 {
-            // last adapter always has 1 option
-            value_map.insert(current, 1);
-            continue;
-        }
+    let chain = Chain::new(&adapters, 3, 4).unwrap();
+    assert!(chain.arrangements().is_zero());
+    assert!(chain.differences().is_err());
+    assert!(chain.mandatory().is_err());
+    assert!(Chain::new(&[0, 1], 3, 3).is_err());
+    assert!(Chain::new(&[1], 0, 3).is_err());
 
-        let mut options = 0;
-        // Get previous adapters we can still connect to:
-        for j in 1..=i {
-            let next = sorted[i - j];
-            if next - current > 3 {
-                break;
-            }
+    // 1, 2, 3, ... 300 with gaps of up to 3 overflows any integer type.
+    let long = (1..=300).collect::<Vec<_>>();
+    let count = Chain::new(&long, 3, 3).unwrap().arrangements();
+    assert!(count > BigUint::from(u64::MAX));
+    assert_eq!(count.to_string().len(), 80);
+}
+
+#[cfg(test)]
+fn is_valid_arrangement(chain: &Chain, arrangement: &[u64]) -> bool {
+    arrangement.first() == chain.joltages.first()
+        && arrangement.last() == chain.joltages.last()
+        && arrangement[1..arrangement.len() - 1]
+            .iter()
+            .all(|a| chain.joltages.contains(a))
+        && arrangement
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1] && pair[1] - pair[0] <= chain.max_gap)
+}
 
-            if let Some(v) = value_map.get(&next) {
-                options += v
+#[test]
+fn test_arrangements() {
+    let chain = Chain::new(&[16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4], 3, 3).unwrap();
+    let all = chain.iter_arrangements().collect::<Vec<_>>();
+    assert_eq!(all.len(), 8);
+    assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(all.iter().all(|a| is_valid_arrangement(&chain, a)));
+    assert_eq!(all[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+    assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+
+    for (k, arrangement) in all.iter().enumerate() {
+        assert_eq!(
+            chain.nth_arrangement(&BigUint::from(k as u64)).as_ref(),
+            Some(arrangement)
+        );
+    }
+    assert_eq!(chain.nth_arrangement(&BigUint::from(8)), None);
+
+    let mut rng = Rng::new(10);
+    let samples = (0..50)
+        .map(|_| chain.random_arrangement(&mut rng).unwrap())
+        .collect::<Vec<_>>();
+    assert!(samples.iter().all(|s| all.contains(s)));
+    let mut rng = Rng::new(10);
+    assert_eq!(chain.random_arrangement(&mut rng).as_ref(), samples.first());
+
+    // Nothing to list when the device can't be reached.
+    let chain = Chain::new(&[1, 2], 3, 4).unwrap();
+    assert_eq!(chain.iter_arrangements().next(), None);
+    assert_eq!(chain.random_arrangement(&mut rng), None);
+
+    // Far too many to list, but we can still take a few, or pick any of them.
+    let chain = Chain::new(&(1..=300).collect::<Vec<_>>(), 3, 3).unwrap();
+    let first = chain.iter_arrangements().take(3).collect::<Vec<_>>();
+    assert_eq!(first[0].len(), 302);
+    assert_eq!(first[1][..299], first[0][..299]);
+    assert_eq!(
+        Some(&first[2]),
+        chain.nth_arrangement(&BigUint::from(2)).as_ref()
+    );
+    let total = chain.arrangements();
+    let last = chain
+        .nth_arrangement(&total.checked_sub(&BigUint::from(1)).unwrap())
+        .unwrap();
+    assert_eq!(last.len(), 102);
+    assert!(is_valid_arrangement(&chain, &last));
+    assert_eq!(chain.nth_arrangement(&total), None);
+    assert!(is_valid_arrangement(
+        &chain,
+        &chain.random_arrangement(&mut rng).unwrap()
+    ));
+}
+
+fn read_chain(options: &Options) -> Result<Chain, String> {
+    options
+        .get_or("max-gap", 3)
+        .and_then(|gap| options.get_or("offset", 3).map(|offset| (gap, offset)))
+        .and_then(|(gap, offset)| {
+            input::read_numeric_input(10).and_then(|a| Chain::new(&a, gap, offset))
+        })
+}
+
+fn format_arrangement(arrangement: &[u64]) -> String {
+    arrangement
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+// Analyses the adapters with `--max-gap` (3) jolts between adapters, and the device `--offset`
+// (3) jolts above the highest adapter.
+pub fn analyse(options: &Options) {
+    let chain = match read_chain(options) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    match chain.differences() {
+        Err(e) => println!("Using all adapters: {}", e),
+        Ok(histogram) => {
+            for (difference, count) in histogram {
+                println!("{} difference(s) of {} jolts", count, difference);
             }
         }
-        value_map.insert(current, options);
     }
 
-    // Since 0 (the outlet) is not part of the adapters, we need to add the values of 1, 2, and 3
-    // (if existing) together to get our result:
-    let mut result = 0;
+    println!("{} possible arrangement(s)", chain.arrangements());
+
+    match chain.mandatory() {
+        Err(e) => println!("{}", e),
+        Ok(adapters) => println!(
+            "{} adapter(s) are in every arrangement: {}",
+            adapters.len(),
+            adapters
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
 
-    if let Some(v) = value_map.get(&1) {
-        result += v
+// Lists arrangements (using the same `--max-gap` and `--offset` as `analyse`): the first `--limit`
+// (10) of them with `--list`, the k-th (from 0, in lexicographic order) with `--nth <k>`, or
+// `--sample <n>` random ones. Sampling uses `--seed`, or a seed based on the time, which is printed
+// so the samples can be reproduced.
+pub fn arrangements(options: &Options) {
+    let applies_to = [("limit", "list"), ("seed", "sample")];
+    if let Some((option, mode)) = applies_to
+        .iter()
+        .find(|(option, mode)| options.has(option) && !options.has(mode))
+    {
+        return eprintln!("--{} can only be used with --{}", option, mode);
     }
-    if let Some(v) = value_map.get(&2) {
-        result += v
+
+    let chain = match read_chain(options) {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    if options.has("list") {
+        let limit = match options.get_or("limit", 10) {
+            Err(e) => return eprintln!("{}", e),
+            Ok(v) => v,
+        };
+        for arrangement in chain.iter_arrangements().take(limit) {
+            println!("{}", format_arrangement(&arrangement));
+        }
     }
-    if let Some(v) = value_map.get(&3) {
-        result += v
+
+    if options.has("nth") {
+        match options.get::<BigUint>("nth") {
+            Err(e) => eprintln!("{}", e),
+            Ok(k) => match chain.nth_arrangement(&k.unwrap_or_default()) {
+                None => eprintln!("There are only {} arrangement(s)", chain.arrangements()),
+                Some(arrangement) => println!("{}", format_arrangement(&arrangement)),
+            },
+        }
     }
 
-    result
-}
+    if options.has("sample") {
+        let default_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let (count, seed) = match options
+            .get_or("sample", 1)
+            .and_then(|count: usize| options.get_or("seed", default_seed).map(|s| (count, s)))
+        {
+            Err(e) => return eprintln!("{}", e),
+            Ok(v) => v,
+        };
 
-#[test]
-fn test_get_possible_connection_count() {
-    assert_eq!(
-        get_possible_connection_count(&vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4]),
-        8
-    );
-    assert_eq!(
-        get_possible_connection_count(&vec![
-            28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
-            8, 17, 7, 9, 4, 2, 34, 10, 3
-        ]),
-        19208
-    );
+        println!("Seed: {}", seed);
+        let mut rng = Rng::new(seed);
+        for _ in 0..count {
+            match chain.random_arrangement(&mut rng) {
+                None => return eprintln!("There is no way to connect the device at all"),
+                Some(arrangement) => println!("{}", format_arrangement(&arrangement)),
+            }
+        }
+    }
 }
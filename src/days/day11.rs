@@ -1,7 +1,12 @@
 use crate::days::day11::Cell::{Floor, Seat};
+#[cfg(test)]
+use crate::util::fuzz::{check, Rng};
 use crate::util::input::read_mapped_input;
-use std::cmp::min;
+use crate::util::options::Options;
+use std::fs::{create_dir_all, write};
 use std::io::{stdout, Write};
+use std::thread::sleep;
+use std::time::Duration;
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 enum Cell {
@@ -22,99 +27,183 @@ fn parse_line(line: String) -> Result<Vec<Cell>, String> {
     line.chars().map(parse_char).collect()
 }
 
+// The simulation works on a grid of cells row by row, so all rows need to be the same width.
+fn check_grid(rows: Vec<Vec<Cell>>) -> Result<Vec<Vec<Cell>>, String> {
+    let width = rows.first().map(|r| r.len()).unwrap_or(0);
+    if let Some(y) = rows.iter().position(|r| r.len() != width) {
+        return Err(format!(
+            "Row {} has {} cells, but the first row has {}",
+            y + 1,
+            rows[y].len(),
+            width
+        ));
+    }
+
+    Ok(rows)
+}
+
 fn read_input() -> Result<Vec<Vec<Cell>>, String> {
-    read_mapped_input(11, parse_line)
+    read_mapped_input(11, parse_line).and_then(check_grid)
 }
 
 // This is basically a game of life. Floor tiles won't ever change, but chairs do; according to
 // there two rules:
 // 1 - If a seat is empty, and so are all seats adjacent to it (in all 8 directions) => it becomes occupied
 // 2 - If a seat is occupied, and at least 4 other seats around it are as well => it becomes empty
+// Part 2 changes which seats count as "around" and the second threshold, so both are described by
+// `Rules`, which can also describe other seating policies.
 
-fn get_surrounding_seats(grid: &Vec<Vec<Cell>>, x: usize, y: usize) -> Vec<&Cell> {
-    let mut result = vec![];
-
-    for ry in if y > 0 { y - 1 } else { 0 }..=min(y + 1, grid.len() - 1) {
-        for rx in if x > 0 { x - 1 } else { 0 }..=min(x + 1, grid[0].len() - 1) {
-            if rx == x && ry == y {
-                continue;
-            }
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Neighbours {
+    // The (up to) 8 cells around a seat
+    Adjacent,
+    // The first seat visible in each of the 8 directions, looking at most this many cells far
+    // (or until the edge of the grid with None)
+    LineOfSight(Option<usize>),
+}
 
-            result.push(&grid[ry][rx])
+impl Neighbours {
+    // Parses "adjacent", "sight" or "sight:<max distance>"
+    fn parse(data: &str) -> Result<Neighbours, String> {
+        match data {
+            "adjacent" => Ok(Neighbours::Adjacent),
+            "sight" => Ok(Neighbours::LineOfSight(None)),
+            _ => match data.strip_prefix("sight:").map(str::parse) {
+                Some(Ok(distance)) if distance > 0 => Ok(Neighbours::LineOfSight(Some(distance))),
+                _ => Err(format!(
+                    "Unknown neighbours '{}', expected adjacent, sight or sight:<distance>",
+                    data
+                )),
+            },
         }
     }
 
-    result
+    // How far to look in every direction.
+    fn max_distance(self) -> Option<usize> {
+        match self {
+            // Looking a single cell far is the same as only looking at adjacent cells
+            Neighbours::Adjacent => Some(1),
+            Neighbours::LineOfSight(distance) => distance,
+        }
+    }
 }
 
-fn get_visible_seat(
-    grid: &Vec<Vec<Cell>>,
-    (start_x, start_y): (usize, usize),
-    (dx, dy): (i32, i32),
-) -> Option<&Cell> {
-    // Safe case to i32, should fit; given the puzzle input.
-    let mut x = start_x as i32;
-    let mut y = start_y as i32;
-
-    let max_y = grid.len() as i32;
-    let max_x = grid[0].len() as i32;
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Rules {
+    neighbours: Neighbours,
+    // An empty seat becomes occupied when at most this many neighbours are occupied
+    occupy: usize,
+    // An occupied seat becomes empty when at least this many neighbours are occupied
+    vacate: usize,
+}
 
-    loop {
-        x += dx;
-        y += dy;
+impl Rules {
+    const PART1: Rules = Rules {
+        neighbours: Neighbours::Adjacent,
+        occupy: 0,
+        vacate: 4,
+    };
+    const PART2: Rules = Rules {
+        neighbours: Neighbours::LineOfSight(None),
+        occupy: 0,
+        vacate: 5,
+    };
 
-        if x < 0 || y < 0 || x >= max_x || y >= max_y {
-            return None;
-        }
+    // Starts from the `--rules` preset (part1 or part2, the default), and overrides whatever is
+    // given in `--neighbours`, `--occupy` or `--vacate`.
+    fn from_options(options: &Options) -> Result<Rules, String> {
+        let mut rules = match options.get_or("rules", "part2".to_owned())?.as_str() {
+            "part1" => Rules::PART1,
+            "part2" => Rules::PART2,
+            other => {
+                return Err(format!(
+                    "Unknown rules '{}', expected part1 or part2",
+                    other
+                ))
+            }
+        };
 
-        match grid.get(y as usize).and_then(|r| r.get(x as usize)) {
-            Some(cell @ Seat(_)) => return Some(cell),
-            _ => continue,
+        if let Some(neighbours) = options.get::<String>("neighbours")? {
+            rules.neighbours = Neighbours::parse(&neighbours)?;
         }
+        rules.occupy = options.get_or("occupy", rules.occupy)?;
+        rules.vacate = options.get_or("vacate", rules.vacate)?;
+        Ok(rules)
     }
 }
 
-fn get_visible_seats(grid: &Vec<Vec<Cell>>, x: usize, y: usize) -> Vec<&Cell> {
-    let mut result = vec![];
-
-    for dx in -1..=1 {
-        for dy in -1..=1 {
-            if dx == 0 && dy == 0 {
-                continue; // Skip useless case
-            }
-            if let Some(c) = get_visible_seat(grid, (x, y), (dx, dy)) {
-                result.push(c);
-            }
+const DIRECTIONS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// The position of the first seat in direction (dx, dy), looking at most `max_distance` cells far.
+fn get_visible_seat(
+    grid: &[Vec<Cell>],
+    (start_x, start_y): (usize, usize),
+    (dx, dy): (i64, i64),
+    max_distance: Option<usize>,
+) -> Option<(usize, usize)> {
+    let mut x = start_x as i64;
+    let mut y = start_y as i64;
+    let mut distance = 0;
+
+    while max_distance.map(|max| distance < max).unwrap_or(true) {
+        x += dx;
+        y += dy;
+        distance += 1;
+
+        match grid
+            .get(y as usize)
+            .and_then(|r| r.get(x as usize))
+            .filter(|_| x >= 0 && y >= 0)
+        {
+            None => return None, // Off the grid
+            Some(Seat(_)) => return Some((x as usize, y as usize)),
+            Some(Floor) => continue,
         }
     }
 
-    result
+    None
+}
+
+#[cfg(test)]
+fn get_occupied_around(grid: &[Vec<Cell>], x: usize, y: usize, neighbours: Neighbours) -> usize {
+    DIRECTIONS
+        .iter()
+        .filter_map(|&d| get_visible_seat(grid, (x, y), d, neighbours.max_distance()))
+        .filter(|&(x, y)| grid[y][x] == Seat(true))
+        .count()
 }
 
 // Run a single 'game of life' cycle, returns true if at least one cell changed state.
-fn run_gol_cycle(
-    grid: &mut Vec<Vec<Cell>>,
-    get_occupied: fn(&Vec<Vec<Cell>>, usize, usize) -> usize,
-    threshold: usize,
-) -> bool {
-    let input = grid.into_iter().map(|l| l.clone()).collect::<Vec<_>>();
+// This looks around every seat again in every cycle; `Simulation` does the same, a lot faster. We
+// keep this one to check (and benchmark) that against.
+#[cfg(test)]
+fn run_gol_cycle(grid: &mut [Vec<Cell>], rules: &Rules) -> bool {
+    let input = grid.to_vec();
     let mut result = false;
 
-    for y in 0..input.len() {
-        let row = &input[y];
-        for x in 0..row.len() {
-            match &row[x] {
+    for (y, row) in input.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            match cell {
                 Floor => continue,
                 Seat(true) => {
                     // Occupied seat, check if it needs to become empty
-                    if get_occupied(&input, x, y) >= threshold {
+                    if get_occupied_around(&input, x, y, rules.neighbours) >= rules.vacate {
                         grid[y][x] = Seat(false);
                         result = true;
                     }
                 }
                 Seat(false) => {
                     // Empty seat, check if it needs to become occupied
-                    if get_occupied(&input, x, y) == 0 {
+                    if get_occupied_around(&input, x, y, rules.neighbours) <= rules.occupy {
                         grid[y][x] = Seat(true);
                         result = true;
                     }
@@ -126,23 +215,196 @@ fn run_gol_cycle(
     result
 }
 
-fn _debug_print_grid(grid: &Vec<Vec<Cell>>) {
+// Runs the seating rules on a grid. Since floor never changes, which seats are neighbours never
+// changes either; so we look those up once, and only keep the state of the seats afterwards.
+// Cells are numbered row by row, so the grid needs to be rectangular (see `check_grid`).
+struct Simulation {
+    width: usize,
+    height: usize,
+    rules: Rules,
+    // The state of every cell, row by row, and a buffer to build the next round in
+    cells: Vec<Cell>,
+    next: Vec<Cell>,
+    // The indexes of the seats around every cell, one cell after the other; those of cell i are
+    // neighbours[first_neighbour[i]..first_neighbour[i + 1]]. One flat list is a lot friendlier to
+    // the cache than a Vec per cell.
+    neighbours: Vec<usize>,
+    first_neighbour: Vec<usize>,
+    // For every cell, how many of the seats around it are occupied. Kept up to date as seats
+    // change, so deciding what a seat does next doesn't need to look around.
+    occupied_around: Vec<usize>,
+    // Which seats need to be looked at in the next round: the ones that changed in the last round,
+    // and the seats around those. Flags rather than a list of indexes, so marking a seat twice is
+    // free, and we go through the seats in order.
+    frontier: Vec<bool>,
+    // The seats that changed in the last round
+    changed: Vec<usize>,
+    round: usize,
+}
+
+impl Simulation {
+    fn new(grid: &[Vec<Cell>], rules: Rules) -> Simulation {
+        let width = grid.first().map(|r| r.len()).unwrap_or(0);
+        let cells = grid.iter().flatten().copied().collect::<Vec<_>>();
+
+        let mut neighbours = vec![];
+        let mut first_neighbour = vec![0];
+        for (y, row) in grid.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if *cell != Floor {
+                    neighbours.extend(
+                        DIRECTIONS
+                            .iter()
+                            .filter_map(|&d| {
+                                get_visible_seat(grid, (x, y), d, rules.neighbours.max_distance())
+                            })
+                            .map(|(x, y)| y * width + x),
+                    );
+                }
+                first_neighbour.push(neighbours.len());
+            }
+        }
+
+        let mut occupied_around = vec![0; cells.len()];
+        for (i, count) in occupied_around.iter_mut().enumerate() {
+            *count = neighbours[first_neighbour[i]..first_neighbour[i + 1]]
+                .iter()
+                .filter(|&&n| cells[n] == Seat(true))
+                .count();
+        }
+
+        // In the first round, every seat needs to be looked at.
+        let frontier = cells.iter().map(|&c| c != Floor).collect::<Vec<_>>();
+
+        Simulation {
+            width,
+            height: grid.len(),
+            rules,
+            next: cells.clone(),
+            cells,
+            neighbours,
+            first_neighbour,
+            occupied_around,
+            frontier,
+            changed: vec![],
+            round: 0,
+        }
+    }
+
+    // Runs one round, and returns how many seats changed.
+    fn step(&mut self) -> usize {
+        self.round += 1;
+        self.changed.clear();
+
+        for i in 0..self.frontier.len() {
+            if !self.frontier[i] {
+                continue;
+            }
+            self.frontier[i] = false;
+            let occupied = self.occupied_around[i];
+            let next = match self.cells[i] {
+                Seat(true) if occupied >= self.rules.vacate => Seat(false),
+                Seat(false) if occupied <= self.rules.occupy => Seat(true),
+                _ => continue,
+            };
+            self.next[i] = next;
+            self.changed.push(i);
+        }
+
+        // Both buffers are the same, except for the seats that changed; so after swapping them,
+        // only those need to be brought up to date in the new buffer.
+        std::mem::swap(&mut self.cells, &mut self.next);
+        for &i in &self.changed {
+            self.next[i] = self.cells[i];
+        }
+
+        // A seat can only change if it, or one of its neighbours, changed. Seeing each other is
+        // symmetric (if A is the first seat B sees in one direction, B is the first seat A sees in
+        // the opposite direction), so those are the neighbours of the changed seats; which are
+        // also the seats whose occupied count changes.
+        for &i in &self.changed {
+            let around = &self.neighbours[self.first_neighbour[i]..self.first_neighbour[i + 1]];
+            for &n in around {
+                if self.cells[i] == Seat(true) {
+                    self.occupied_around[n] += 1;
+                } else {
+                    self.occupied_around[n] -= 1;
+                }
+            }
+            for &n in std::iter::once(&i).chain(around) {
+                self.frontier[n] = true;
+            }
+        }
+
+        self.changed.len()
+    }
+
+    // Runs rounds until nothing changes any more, or after `max_rounds`. Returns the number of
+    // rounds in which seats changed.
+    fn run(&mut self, max_rounds: usize) -> usize {
+        let mut rounds = 0;
+        while rounds < max_rounds && self.step() > 0 {
+            rounds += 1;
+        }
+        rounds
+    }
+
+    // Whether the last round didn't change anything, so no round after it will either.
+    fn is_stable(&self) -> bool {
+        self.round > 0 && self.changed.is_empty()
+    }
+
+    fn occupied(&self) -> usize {
+        self.cells.iter().filter(|&&c| c == Seat(true)).count()
+    }
+
+    fn grid(&self) -> Vec<Vec<Cell>> {
+        (0..self.height)
+            .map(|y| self.cells[y * self.width..(y + 1) * self.width].to_vec())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn count_occupied(grid: &[Vec<Cell>]) -> usize {
+    grid.iter().flatten().filter(|&&c| c == Seat(true)).count()
+}
+
+fn render(grid: &[Vec<Cell>]) -> String {
+    let mut result = String::new();
     for row in grid {
-        let line: String = row
-            .iter()
-            .map(|c| match c {
-                Floor => '.',
-                Seat(true) => '#',
-                Seat(false) => 'L',
-            })
-            .collect();
-        println!("{}", line);
+        result.extend(row.iter().map(|c| match c {
+            Floor => '.',
+            Seat(true) => '#',
+            Seat(false) => 'L',
+        }));
+        result.push('\n');
     }
-    println!();
+    result
+}
 
-    if let Err(e) = stdout().flush() {
-        eprintln!("{}", e);
+// A binary PPM image of the grid, with every cell `scale` pixels wide and high: floor is dark
+// grey, empty seats are green and occupied seats red.
+fn to_ppm(grid: &[Vec<Cell>], scale: usize) -> Vec<u8> {
+    let width = grid.first().map(|r| r.len()).unwrap_or(0);
+    let mut result = format!("P6\n{} {}\n255\n", width * scale, grid.len() * scale).into_bytes();
+    for row in grid {
+        let line = row
+            .iter()
+            .flat_map(|c| {
+                let colour: [u8; 3] = match c {
+                    Floor => [40, 40, 40],
+                    Seat(false) => [40, 200, 80],
+                    Seat(true) => [220, 50, 50],
+                };
+                colour.repeat(scale)
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..scale {
+            result.extend_from_slice(&line);
+        }
     }
+    result
 }
 
 #[test]
@@ -186,46 +448,18 @@ fn test_run_gol_cycle() {
         parse_line("#.#LLLL.##".to_owned()).unwrap(),
     ];
 
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        true
-    );
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), true);
     assert_eq!(input, cycle1);
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        true
-    );
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), true);
     assert_eq!(input, cycle2);
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle1, 4),
-        false
-    );
-}
-
-fn get_occupied_around_puzzle1(grid: &Vec<Vec<Cell>>, x: usize, y: usize) -> usize {
-    get_surrounding_seats(grid, x, y)
-        .into_iter()
-        .filter(|c| match c {
-            Seat(true) => true,
-            _ => false,
-        })
-        .count()
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART1), false);
 }
 
 pub fn puzzle1() {
-    let mut grid = match read_input() {
+    let grid = match read_input() {
         Err(e) => {
             eprintln!("{}", e);
             return;
@@ -234,17 +468,11 @@ pub fn puzzle1() {
     };
 
     // Run simulation until stable:
-    while run_gol_cycle(&mut grid, get_occupied_around_puzzle1, 4) {}
+    let mut simulation = Simulation::new(&grid, Rules::PART1);
+    simulation.run(usize::MAX);
 
     // Count number of occupied seats:
-    let occupied_seats = grid
-        .into_iter()
-        .flat_map(|row| row)
-        .filter(|c| match c {
-            Seat(true) => true,
-            _ => false,
-        })
-        .count();
+    let occupied_seats = simulation.occupied();
     println!("Puzzle 1: There are {} occupied seats", occupied_seats);
 }
 
@@ -289,37 +517,15 @@ fn test_run_gol_cycle2() {
         parse_line("#.LLLLL.L#".to_owned()).unwrap(),
     ];
 
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
     assert_eq!(input, cycle1);
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
-    _debug_print_grid(&input);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
     assert_eq!(input, cycle2);
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        true
-    );
-    assert_eq!(
-        run_gol_cycle(&mut input, get_occupied_around_puzzle2, 5),
-        false
-    );
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), true);
+    assert_eq!(run_gol_cycle(&mut input, &Rules::PART2), false);
 }
 
 #[test]
@@ -337,21 +543,21 @@ fn test_get_occupied_around_puzzle2() {
         parse_line("#.#####.##".to_owned()).unwrap(),
     ];
 
-    assert_eq!(get_occupied_around_puzzle2(&state, 0, 0), 3);
-}
-
-fn get_occupied_around_puzzle2(grid: &Vec<Vec<Cell>>, x: usize, y: usize) -> usize {
-    get_visible_seats(grid, x, y)
-        .into_iter()
-        .filter(|c| match c {
-            Seat(true) => true,
-            _ => false,
-        })
-        .count()
+    assert_eq!(
+        get_occupied_around(&state, 0, 0, Neighbours::LineOfSight(None)),
+        3
+    );
+    assert_eq!(get_occupied_around(&state, 0, 0, Neighbours::Adjacent), 2);
+    // Looking at most two cells far, (0, 0) sees the seats at (2, 0), (0, 1) and (1, 1)
+    assert_eq!(
+        get_occupied_around(&state, 0, 0, Neighbours::LineOfSight(Some(2))),
+        3
+    );
+    assert_eq!(get_occupied_around(&state, 9, 9, Neighbours::Adjacent), 2);
 }
 
 pub fn puzzle2() {
-    let mut grid = match read_input() {
+    let grid = match read_input() {
         Err(e) => {
             eprintln!("{}", e);
             return;
@@ -360,16 +566,438 @@ pub fn puzzle2() {
     };
 
     // Run simulation until stable:
-    while run_gol_cycle(&mut grid, get_occupied_around_puzzle2, 5) {}
+    let mut simulation = Simulation::new(&grid, Rules::PART2);
+    simulation.run(usize::MAX);
 
     // Count number of occupied seats:
-    let occupied_seats = grid
-        .into_iter()
-        .flat_map(|row| row)
-        .filter(|c| match c {
-            Seat(true) => true,
-            _ => false,
-        })
-        .count();
+    let occupied_seats = simulation.occupied();
     println!("Puzzle 2: There are {} occupied seats", occupied_seats);
 }
+
+#[test]
+fn test_rules() {
+    let parse = |args: &[&str]| {
+        Options::parse(args.iter().map(|a| a.to_string())).and_then(|o| Rules::from_options(&o))
+    };
+
+    assert_eq!(parse(&[]), Ok(Rules::PART2));
+    assert_eq!(parse(&["--rules", "part1"]), Ok(Rules::PART1));
+    assert_eq!(
+        parse(&[
+            "--rules",
+            "part1",
+            "--neighbours",
+            "sight:3",
+            "--vacate",
+            "2"
+        ]),
+        Ok(Rules {
+            neighbours: Neighbours::LineOfSight(Some(3)),
+            occupy: 0,
+            vacate: 2
+        })
+    );
+    assert!(parse(&["--rules", "part3"]).is_err());
+    assert!(parse(&["--neighbours", "sight:0"]).is_err());
+    assert!(parse(&["--occupy", "-1"]).is_err());
+
+    // Line of sight limited to one cell is the same as only looking at adjacent seats.
+    let mut grid = read_example();
+    let mut limited = read_example();
+    let one = Rules {
+        neighbours: Neighbours::LineOfSight(Some(1)),
+        ..Rules::PART1
+    };
+    while run_gol_cycle(&mut grid, &Rules::PART1) {
+        assert!(run_gol_cycle(&mut limited, &one));
+        assert_eq!(grid, limited);
+    }
+    assert_eq!(count_occupied(&grid), 37);
+}
+
+#[cfg(test)]
+fn read_example() -> Vec<Vec<Cell>> {
+    [
+        "L.LL.LL.LL",
+        "LLLLLLL.LL",
+        "L.L.L..L..",
+        "LLLL.LL.LL",
+        "L.LL.LL.LL",
+        "L.LLLLL.LL",
+        "..L.L.....",
+        "LLLLLLLLLL",
+        "L.LLLLLL.L",
+        "L.LLLLL.LL",
+    ]
+    .iter()
+    .map(|l| parse_line(l.to_string()).unwrap())
+    .collect()
+}
+
+#[cfg(test)]
+fn random_grid(rng: &mut Rng, width: usize, height: usize, cells: &[Cell]) -> Vec<Vec<Cell>> {
+    (0..height)
+        .map(|_| (0..width).map(|_| *rng.pick(cells)).collect())
+        .collect()
+}
+
+#[test]
+fn test_check_grid() {
+    let grid = |lines: &[&str]| {
+        check_grid(
+            lines
+                .iter()
+                .map(|l| parse_line(l.to_string()).unwrap())
+                .collect(),
+        )
+    };
+
+    assert!(grid(&["L.L", "#L."]).is_ok());
+    assert!(grid(&[]).is_ok());
+    assert_eq!(
+        grid(&["L.L", "", "#L."]).err(),
+        Some("Row 2 has 0 cells, but the first row has 3".to_owned())
+    );
+    assert!(grid(&["L.L", "#L.L"]).is_err());
+}
+
+#[test]
+fn test_simulation() {
+    let mut simulation = Simulation::new(&read_example(), Rules::PART1);
+    assert_eq!(simulation.step(), 71);
+    assert_eq!(simulation.occupied(), 71);
+    assert_eq!(simulation.run(usize::MAX), 4);
+    assert!(simulation.is_stable());
+    assert_eq!(simulation.occupied(), 37);
+
+    let mut simulation = Simulation::new(&read_example(), Rules::PART2);
+    assert_eq!(simulation.run(3), 3);
+    assert!(!simulation.is_stable());
+    simulation.run(usize::MAX);
+    assert_eq!(simulation.occupied(), 26);
+
+    let mut empty = Simulation::new(&[], Rules::PART1);
+    assert_eq!(empty.run(10), 0);
+    assert!(empty.grid().is_empty());
+}
+
+// The simulation should do exactly the same as looking at every seat, every round; also for rules
+// that never settle, and grids that aren't made of empty seats only.
+#[test]
+fn test_simulation_matches_cycles() {
+    check(
+        "day 11 simulation matches run_gol_cycle",
+        |rng| {
+            let (width, height) = (rng.range(1, 12) as usize, rng.range(1, 12) as usize);
+            let mut grid = random_grid(rng, width, height, &[Floor, Seat(false), Seat(true)]);
+            // Sometimes make a row shorter or longer (possibly empty, like a blank line)
+            if rng.chance(20) {
+                let y = rng.below(height);
+                grid[y] = vec![Seat(false); rng.below(width + 2)];
+            }
+            let neighbours = match rng.below(3) {
+                0 => Neighbours::Adjacent,
+                1 => Neighbours::LineOfSight(None),
+                _ => Neighbours::LineOfSight(Some(rng.range(1, 4) as usize)),
+            };
+            let rules = Rules {
+                neighbours,
+                occupy: rng.below(4),
+                vacate: rng.below(9),
+            };
+            (grid, rules)
+        },
+        |(grid, rules)| {
+            let rectangular = grid.iter().all(|r| r.len() == grid[0].len());
+            let grid = match check_grid(grid.clone()) {
+                Err(_) if !rectangular => return Ok(()),
+                Ok(_) if !rectangular => return Err("A ragged grid was accepted".to_owned()),
+                Err(e) => return Err(e),
+                Ok(v) => v,
+            };
+            let mut expected = grid.clone();
+            let mut simulation = Simulation::new(&grid, *rules);
+            for round in 1..=20 {
+                let before = expected.clone();
+                run_gol_cycle(&mut expected, rules);
+                let changes = simulation.step();
+                let changed = before
+                    .iter()
+                    .flatten()
+                    .zip(expected.iter().flatten())
+                    .filter(|(a, b)| a != b)
+                    .count();
+                if simulation.grid() != expected || changes != changed {
+                    return Err(format!(
+                        "Round {}: {} changes to {:?}, expected {} to {:?}",
+                        round,
+                        changes,
+                        simulation.grid(),
+                        changed,
+                        expected
+                    ));
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+// Benchmark comparing `run_gol_cycle` against `Simulation`, for both puzzles on the puzzle input
+// (if there is one) and on a big grid that, like the input, starts with empty seats and some
+// floor. That grid never settles with the part 1 rules, so both run for at most 500 rounds. When
+// almost every seat flips every round, keeping track of the changes costs as much as it saves.
+// Run with `cargo test --release -- --ignored --nocapture bench_`.
+#[test]
+#[ignore]
+fn bench_simulation() {
+    use std::time::Instant;
+
+    let mut cells = vec![Seat(false); 6];
+    cells.push(Floor);
+    let generated = random_grid(&mut Rng::new(11), 200, 200, &cells);
+    let max_rounds = 500;
+    for (name, grid) in [("generated", Ok(generated)), ("input", read_input())] {
+        let grid = match grid {
+            Err(_) => continue,
+            Ok(v) => v,
+        };
+        for &rules in &[Rules::PART1, Rules::PART2] {
+            let start = Instant::now();
+            let mut cycles = grid.clone();
+            let mut cycle_rounds = 0;
+            while cycle_rounds < max_rounds && run_gol_cycle(&mut cycles, &rules) {
+                cycle_rounds += 1;
+            }
+            let cycle_time = start.elapsed();
+
+            let start = Instant::now();
+            let mut simulation = Simulation::new(&grid, rules);
+            let setup_time = start.elapsed();
+            let simulation_rounds = simulation.run(max_rounds);
+            let simulation_time = start.elapsed();
+
+            assert_eq!(cycle_rounds, simulation_rounds);
+            assert_eq!(simulation.grid(), cycles);
+            println!(
+                "{} {:?}, {} rounds: run_gol_cycle {:?}, Simulation {:?} (of which setup {:?})",
+                name, rules.neighbours, cycle_rounds, cycle_time, simulation_time, setup_time
+            );
+        }
+    }
+}
+
+// Where to show every round of a simulation.
+#[derive(Eq, PartialEq, Debug)]
+enum Frames {
+    // Only print the statistics of every round
+    Stats,
+    // Redraws the grid in the terminal, waiting this long after every round
+    Terminal(Duration),
+    // Numbered files in a directory, as text or PPM images (with cells `scale` pixels wide)
+    Text(String),
+    Ppm(String, usize),
+}
+
+impl Frames {
+    // `--animate` (with a `--delay` of 100 ms between rounds), `--frames <directory>` with a
+    // `--format` of text (the default) or ppm (with `--scale`, 4 pixels per cell), or just
+    // `--stats`. None if we don't need to show every round at all.
+    fn from_options(options: &Options) -> Result<Option<Frames>, String> {
+        // Rather than silently ignoring options that don't apply:
+        let applies_to = [
+            ("delay", "animate"),
+            ("format", "frames"),
+            ("scale", "frames"),
+        ];
+        if let Some((option, mode)) = applies_to
+            .iter()
+            .find(|(option, mode)| options.has(option) && !options.has(mode))
+        {
+            return Err(format!("--{} can only be used with --{}", option, mode));
+        }
+        if options.has("animate") && options.has("frames") {
+            return Err("Use either --animate or --frames, not both".to_owned());
+        }
+
+        if options.has("animate") {
+            return Ok(Some(Frames::Terminal(Duration::from_millis(
+                options.get_or("delay", 100)?,
+            ))));
+        }
+
+        let directory = match options.get::<String>("frames")? {
+            None if options.has("stats") => return Ok(Some(Frames::Stats)),
+            None => return Ok(None),
+            Some(d) => d,
+        };
+        match options.get_or("format", "text".to_owned())?.as_str() {
+            "text" if options.has("scale") => {
+                Err("--scale can only be used with --format ppm".to_owned())
+            }
+            "text" => Ok(Some(Frames::Text(directory))),
+            "ppm" => match options.get_or("scale", 4)? {
+                0 => Err("The scale needs to be at least 1".to_owned()),
+                scale => Ok(Some(Frames::Ppm(directory, scale))),
+            },
+            other => Err(format!(
+                "Unknown frame format '{}', expected text or ppm",
+                other
+            )),
+        }
+    }
+
+    fn prepare(&self) -> Result<(), String> {
+        match self {
+            Frames::Text(directory) | Frames::Ppm(directory, _) => create_dir_all(directory)
+                .map_err(|e| format!("Could not create {}: {}", directory, e)),
+            Frames::Terminal(_) => {
+                // Clear the screen once, every frame then draws over the previous one.
+                print!("\x1b[2J");
+                Ok(())
+            }
+            Frames::Stats => Ok(()),
+        }
+    }
+
+    fn show(&self, round: usize, grid: &[Vec<Cell>], stats: &str) -> Result<(), String> {
+        let (path, data) = match self {
+            Frames::Stats => {
+                println!("{}", stats);
+                return Ok(());
+            }
+            Frames::Terminal(delay) => {
+                // Move the cursor back to the top-left, and clear the rest of the stats line
+                println!("\x1b[H{}{}\x1b[K", render(grid), stats);
+                stdout().flush().map_err(|e| e.to_string())?;
+                sleep(*delay);
+                return Ok(());
+            }
+            Frames::Text(directory) => (
+                format!("{}/frame_{:04}.txt", directory, round),
+                render(grid).into_bytes(),
+            ),
+            Frames::Ppm(directory, scale) => (
+                format!("{}/frame_{:04}.ppm", directory, round),
+                to_ppm(grid, *scale),
+            ),
+        };
+
+        write(&path, data).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        println!("{}", stats);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_frames() {
+    let grid = vec![
+        parse_line("#.L".to_owned()).unwrap(),
+        parse_line("LL#".to_owned()).unwrap(),
+    ];
+    assert_eq!(render(&grid), "#.L\nLL#\n");
+
+    let ppm = to_ppm(&grid, 2);
+    let header = b"P6\n6 4\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(ppm.len(), header.len() + 6 * 4 * 3);
+    // The first two pixels of the first two lines are an occupied seat
+    let pixels = &ppm[header.len()..];
+    assert_eq!(pixels[..6], pixels[18..24]);
+    assert_eq!(pixels[..3], [220, 50, 50]);
+    assert_eq!(pixels[6..9], [40, 40, 40]);
+
+    let frames = |args: &[&str]| {
+        Options::parse(args.iter().map(|a| a.to_string())).and_then(|o| Frames::from_options(&o))
+    };
+    assert_eq!(frames(&[]), Ok(None));
+    assert_eq!(frames(&["--stats"]), Ok(Some(Frames::Stats)));
+    assert_eq!(
+        frames(&["--animate", "--delay", "5"]),
+        Ok(Some(Frames::Terminal(Duration::from_millis(5))))
+    );
+    assert_eq!(
+        frames(&["--frames", "out", "--format", "ppm"]),
+        Ok(Some(Frames::Ppm("out".to_owned(), 4)))
+    );
+    assert!(frames(&["--frames", "out", "--format", "gif"]).is_err());
+    assert_eq!(
+        frames(&["--delay", "5"]),
+        Err("--delay can only be used with --animate".to_owned())
+    );
+    assert!(frames(&["--animate", "--scale", "2"]).is_err());
+    assert!(frames(&["--frames", "out", "--scale", "2"]).is_err());
+    assert!(frames(&["--animate", "--frames", "out"]).is_err());
+    assert!(frames(&["--frames"]).is_err());
+}
+
+// Simulates `Rules::from_options` until the seating is stable, or for at most `--max-rounds`
+// (1000) rounds; some rules keep changing seats forever. With `--stats`, `--animate` or `--frames`
+// (see `Frames::from_options`), also shows every round, with how many seats changed in it and how
+// many are occupied after it. Frames are numbered by round, with the starting grid as round 0; so
+// they can be turned into a video or GIF.
+pub fn simulate(options: &Options) {
+    let (rules, max_rounds, frames) = match Rules::from_options(options)
+        .and_then(|r| options.get_or("max-rounds", 1000).map(|m: usize| (r, m)))
+        .and_then(|(r, m)| Frames::from_options(options).map(|f| (r, m, f)))
+    {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    let mut simulation = match read_input() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(grid) => Simulation::new(&grid, rules),
+    };
+
+    let stats = |round, changes, simulation: &Simulation| {
+        format!(
+            "Round {}: {} changes, {} occupied seats",
+            round,
+            changes,
+            simulation.occupied()
+        )
+    };
+    if let Some(frames) = &frames {
+        if let Err(e) = frames
+            .prepare()
+            .and_then(|_| frames.show(0, &simulation.grid(), &stats(0, 0, &simulation)))
+        {
+            return eprintln!("{}", e);
+        }
+    }
+
+    let mut rounds = 0;
+    while rounds < max_rounds {
+        let changes = simulation.step();
+        if changes == 0 {
+            break;
+        }
+        rounds += 1;
+        if let Some(frames) = &frames {
+            if let Err(e) = frames.show(
+                rounds,
+                &simulation.grid(),
+                &stats(rounds, changes, &simulation),
+            ) {
+                return eprintln!("{}", e);
+            }
+        }
+    }
+
+    if simulation.is_stable() {
+        println!(
+            "{:?}: stable after {} rounds, with {} occupied seats",
+            rules,
+            rounds,
+            simulation.occupied()
+        );
+    } else {
+        println!(
+            "{:?}: not stable after {} rounds, {} occupied seats",
+            rules,
+            rounds,
+            simulation.occupied()
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use crate::days::day12::Direction::{EAST, NORTH, SOUTH, WEST};
 use crate::util::input::read_mapped_input;
+use crate::util::parse::LinePattern;
+use lazy_static::lazy_static;
 
 #[derive(Eq, PartialEq, Debug)]
 enum Direction {
@@ -15,22 +17,12 @@ struct Instruction {
     value: i128,
 }
 
+lazy_static! {
+    static ref INSTRUCTION: LinePattern = LinePattern::new(r"^([NESWFLR])(\d+)$");
+}
+
 fn parse_line(line: String) -> Result<Instruction, String> {
-    let operation = line
-        .chars()
-        .nth(0)
-        .and_then(|c| match c {
-            'N' | 'E' | 'S' | 'W' | 'F' | 'L' | 'R' => Some(c),
-            _ => None,
-        })
-        .ok_or(format!(
-            "Could not read a valid operation from line '{}'",
-            line
-        ))?;
-
-    let value = line[1..line.len()]
-        .parse::<i128>()
-        .map_err(|e| format!("Could not parse value: {}", e))?;
+    let (operation, value) = INSTRUCTION.parse(&line)?;
 
     Ok(Instruction { operation, value })
 }
@@ -1,5 +1,7 @@
 use crate::days::day14::Mode::{CLEAR, FLUX, SET};
-use crate::util::input::read_raw_input;
+use crate::util::input::read_mapped_input;
+use crate::util::parse::LinePattern;
+use lazy_static::lazy_static;
 use std::collections::HashMap;
 
 #[derive(Eq, PartialEq, Debug)]
@@ -64,49 +66,70 @@ fn test_mask() {
     assert_eq!(apply_mask(0, &mask), 64);
 }
 
-pub fn puzzle1() {
-    let lines =
-        match read_raw_input(14).map(|d| d.split("\n").map(str::to_owned).collect::<Vec<_>>()) {
-            Err(e) => {
-                eprintln!("{}", e);
-                return;
-            }
-            Ok(v) => v,
-        };
+#[derive(Eq, PartialEq, Debug)]
+enum Command {
+    SetMask(Vec<Mask>),
+    Write(u128, u128),
+}
 
-    let mut memory: HashMap<String, u128> = HashMap::new();
-    let mut mask: Vec<Mask> = vec![];
+lazy_static! {
+    static ref MASK: LinePattern = LinePattern::new(r"^mask = ([X01]+)$");
+    static ref WRITE: LinePattern = LinePattern::new(r"^mem\[(\d+)\] = (\d+)$");
+}
 
-    for line in lines {
-        // Line can be two things:
-        // mask = [X01]+ => representing the new bitmask
-        // mem\[\d+\] = \d+ => set value at memory
-        if line[0..7].eq("mask = ") {
-            mask = read_memory_mask(&line[7..]);
+fn parse_line(line: String) -> Result<Command, String> {
+    // Line can be two things:
+    // mask = [X01]+ => representing the new bitmask
+    // mem\[\d+\] = \d+ => set value at memory
+    if MASK.matches(&line) {
+        let (mask,): (String,) = MASK.parse(&line)?;
+        return Ok(Command::SetMask(read_memory_mask(&mask)));
+    }
+
+    let (address, value) = WRITE.parse(&line)?;
+    Ok(Command::Write(address, value))
+}
+
+#[test]
+fn test_parse_line() {
+    assert_eq!(
+        parse_line("mem[8] = 11".to_owned()),
+        Ok(Command::Write(8, 11))
+    );
+    assert_eq!(
+        parse_line("mask = X1".to_owned()),
+        Ok(Command::SetMask(vec![
+            Mask {
+                value: 1,
+                mode: SET
+            },
+            Mask {
+                value: 2,
+                mode: FLUX
+            }
+        ]))
+    );
+    assert!(parse_line("mem[8] = x".to_owned()).is_err());
+}
+
+pub fn puzzle1() {
+    let commands = match read_mapped_input(14, parse_line) {
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
+        Ok(v) => v,
+    };
+
+    let mut memory: HashMap<u128, u128> = HashMap::new();
+    let mut mask: Vec<Mask> = vec![];
 
-        if line[0..4].eq("mem[") {
-            let address = line
-                .chars()
-                .skip(4)
-                .take_while(|c| c.ne(&']'))
-                .collect::<String>();
-            let value = match line
-                .chars()
-                .skip(5 + address.len())
-                .skip_while(|c| !c.is_numeric())
-                .take_while(|c| c.is_numeric())
-                .collect::<String>()
-                .parse::<u128>()
-            {
-                Err(e) => {
-                    eprintln!("Could not parse input '{}': {}", line, e);
-                    return;
-                }
-                Ok(v) => v,
-            };
-
-            memory.insert(address, apply_mask(value, &mask));
+    for command in commands {
+        match command {
+            Command::SetMask(m) => mask = m,
+            Command::Write(address, value) => {
+                memory.insert(address, apply_mask(value, &mask));
+            }
         }
     }
 
@@ -168,14 +191,13 @@ fn test_write_flux_values() {
 }
 
 pub fn puzzle2() {
-    let lines =
-        match read_raw_input(14).map(|d| d.split("\n").map(str::to_owned).collect::<Vec<_>>()) {
-            Err(e) => {
-                eprintln!("{}", e);
-                return;
-            }
-            Ok(v) => v,
-        };
+    let commands = match read_mapped_input(14, parse_line) {
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+        Ok(v) => v,
+    };
 
     // So,this time, the mask works quite differently.
     // It'll mutate the memory address by the following rules:
@@ -185,45 +207,10 @@ pub fn puzzle2() {
     let mut memory: HashMap<u128, u128> = HashMap::new();
     let mut mask: Vec<Mask> = vec![];
 
-    for line in lines {
-        // Line can be two things:
-        // mask = [X01]+ => representing the new bitmask
-        // mem\[\d+\] = \d+ => set value at memory
-        if line[0..7].eq("mask = ") {
-            mask = read_memory_mask(&line[7..]);
-        }
-
-        if line[0..4].eq("mem[") {
-            let address = match line
-                .chars()
-                .skip(4)
-                .take_while(|c| c.ne(&']'))
-                .collect::<String>()
-                .parse::<u128>()
-            {
-                Err(e) => {
-                    eprintln!("Could not parse address '{}': {}", line, e);
-                    return;
-                }
-                Ok(v) => v,
-            };
-
-            let value = match line
-                .chars()
-                .skip_while(|c| c.ne(&'=')) // Skip until the assignment op
-                .skip_while(|c| !c.is_numeric()) // Skip any non-numeric
-                .take_while(|c| c.is_numeric())
-                .collect::<String>()
-                .parse::<u128>()
-            {
-                Err(e) => {
-                    eprintln!("Could not parse input '{}': {}", line, e);
-                    return;
-                }
-                Ok(v) => v,
-            };
-
-            write_value(&mut memory, address, value, &mask);
+    for command in commands {
+        match command {
+            Command::SetMask(m) => mask = m,
+            Command::Write(address, value) => write_value(&mut memory, address, value, &mask),
         }
     }
 
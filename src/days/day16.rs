@@ -1,82 +1,67 @@
+use crate::util::assignment::solve;
 use crate::util::input::read_raw_input;
-use std::collections::HashMap;
+use crate::util::parse::LinePattern;
+use crate::util::sections::Sections;
+use crate::util::symbols::{Interner, Symbol};
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
 use std::ops::Range;
 
 #[derive(Eq, PartialEq, Debug, Hash)]
 struct Field {
-    name: String,
+    name: Symbol,
     validity: Vec<Range<u128>>,
 }
 
-fn parse_range(data: &str) -> Result<Range<u128>, String> {
-    let parts = data
-        .split("-")
-        .map(str::trim)
-        .map(|s| s.parse::<u128>().map_err(|e| format!("{} ({})", e, s)))
-        .collect::<Result<Vec<_>, _>>()?;
-    if parts.len() != 2 {
-        return Err(format!(
-            "Expected two parts for the range, but got {} in {}",
-            parts.len(),
-            data
-        ));
-    }
-
-    let lower = parts[0];
-    let upper = parts[1] + 1; // upper is inclusive, range is exclusive
-
-    return Ok(lower..upper);
+lazy_static! {
+    static ref FIELD: LinePattern = LinePattern::new(r"^([^:]+): (.+)$");
+    static ref RANGE: LinePattern = LinePattern::new(r"^(\d+)-(\d+)$");
 }
 
-fn parse_input() -> Result<(Vec<Field>, Vec<u128>, Vec<Vec<u128>>), String> {
-    let lines = read_raw_input(16).map(|d| d.split("\n").map(str::to_owned).collect::<Vec<_>>())?;
-    let mut i = 0;
-
-    let mut fields = vec![];
-    loop {
-        // We read lines as fields until we encounter a blank one.
-        let line = &lines[i];
-        i += 1;
+fn parse_range(data: &str) -> Result<Range<u128>, String> {
+    let (lower, upper): (u128, u128) = RANGE.parse(data.trim())?;
 
-        if line.len() == 0 {
-            break;
-        }
+    // upper is inclusive, range is exclusive
+    Ok(lower..(upper + 1))
+}
 
-        // Name is the line until the ':'
-        let name = line.chars().take_while(|c| c.ne(&':')).collect::<String>();
-        // The rest needs to be split on " or ", then "-" and parsed to a range
-        let validity = line
-            .chars()
-            .skip_while(|c| c.ne(&':'))
-            .skip(1)
-            .collect::<String>()
-            .split(" or ")
-            .map(parse_range)
-            .collect::<Result<Vec<_>, _>>()?;
-
-        fields.push(Field { name, validity });
-    }
+type Input = (Interner, Vec<Field>, Vec<u128>, Vec<Vec<u128>>);
 
-    // expect next line to be "your ticket:", so we're skipping it.
-    i += 1;
-    let own_ticket = lines[i]
-        .split(",")
-        .map(|v| v.parse::<u128>().map_err(|e| format!("{} ({})", e, v)))
+fn parse_field(line: &str, names: &mut Interner) -> Result<Field, String> {
+    // Name is the line until the ':', the rest needs to be split on " or " and parsed to ranges
+    let (name, ranges): (String, String) = FIELD.parse(line)?;
+    let validity = ranges
+        .split(" or ")
+        .map(parse_range)
         .collect::<Result<Vec<_>, _>>()?;
 
-    // Next lines are a blank and the "other tickets:", so we're also skipping that. (+1 to move past the own ticket line we just parsed)
-    i += 3;
+    Ok(Field {
+        name: names.intern(&name),
+        validity,
+    })
+}
 
-    let other_tickets = lines[i..]
-        .iter()
-        .map(|l| {
-            l.split(",")
-                .map(|v| v.parse::<u128>().map_err(|e| format!("{} ({})", e, v)))
-                .collect::<Result<Vec<_>, _>>()
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+fn parse_ticket(line: &str) -> Result<Vec<u128>, String> {
+    line.split(",")
+        .map(|v| v.parse::<u128>().map_err(|e| format!("{} ({})", e, v)))
+        .collect()
+}
 
-    Ok((fields, own_ticket, other_tickets))
+fn parse_input() -> Result<Input, String> {
+    let data = read_raw_input(16)?;
+    // The input has the fields, followed by a "your ticket:" and a "nearby tickets:" section.
+    let sections = Sections::split(&data).expect_count(3)?;
+
+    let mut names = Interner::new();
+    let fields = sections
+        .at(0)?
+        .parse_lines(|l| parse_field(l, &mut names))?;
+    let own_ticket = sections.named("your ticket")?.parse_single(parse_ticket)?;
+    let other_tickets = sections
+        .named("nearby tickets")?
+        .parse_lines(parse_ticket)?;
+
+    Ok((names, fields, own_ticket, other_tickets))
 }
 
 fn is_valid_for_any_field(value: &u128, fields: &Vec<Field>) -> bool {
@@ -94,7 +79,7 @@ fn is_valid_for_field(value: &u128, field: &Field) -> bool {
 }
 
 pub fn puzzle1() {
-    let (fields, _, other_tickets) = match parse_input() {
+    let (_, fields, _, other_tickets) = match parse_input() {
         Err(e) => {
             eprintln!("{}", e);
             return;
@@ -119,7 +104,7 @@ pub fn puzzle1() {
     );
 }
 pub fn puzzle2() {
-    let (fields, my_ticket, other_tickets) = match parse_input() {
+    let (names, fields, my_ticket, other_tickets) = match parse_input() {
         Err(e) => {
             return eprintln!("{}", e);
         }
@@ -134,89 +119,37 @@ pub fn puzzle2() {
 
     // Then, figure out which field is at which index.
     // 1: Gather which indexes fit the fields.
-    // 2: Try finding unique ones:
-    // 2a: Get the ones with one index, and assign those
-    // 2b: Remove those indexes from the other fields, and continue
-
-    let mut fixed_indexes = vec![];
-    let mut indexed_fields: HashMap<&Field, usize> = HashMap::new();
-
-    // DEBUG: Print all fields with applicable indexes:
+    // 2: Let the solver figure out a unique assignment of fields to indexes.
+    let mut candidates: HashMap<Symbol, HashSet<usize>> = HashMap::new();
     for field in &fields {
-        let mut indexes = vec![];
-        let mut indexes_with_own = vec![];
-
-        for i in 0..fields.len() {
-            if valid_tickets
-                .iter()
-                .all(|v| is_valid_for_field(&v[i], field))
-            {
-                indexes.push(i);
-                if is_valid_for_field(&my_ticket[i], field) {
-                    indexes_with_own.push(i);
-                }
-            }
-        }
-
-        println!(
-            "DEBUG: {:?} => {:?} / {:?}",
-            field, indexes, indexes_with_own
-        )
-    }
-
-    loop {
-        let todo = fields
-            .iter()
-            .filter(|f| match indexed_fields.get(f) {
-                Some(_) => false,
-                None => true,
-            })
-            .collect::<Vec<_>>();
-
-        if todo.len() == 0 {
-            break;
-        }
-
-        let mut assigned = false;
-
-        for field in todo {
-            let mut indexes = vec![];
-
-            for i in 0..fields.len() {
-                if fixed_indexes.contains(&i) {
-                    continue;
-                }
-
-                if valid_tickets
+        let indexes = (0..fields.len())
+            .filter(|i| {
+                valid_tickets
                     .iter()
-                    .all(|v| is_valid_for_field(&v[i], field))
-                    && is_valid_for_field(&my_ticket[i], field)
-                {
-                    indexes.push(i);
-                }
-            }
-
-            if indexes.len() == 1 {
-                let field_index = indexes[0];
-                println!("Fixed field {:?} to index {}", field, field_index);
-                fixed_indexes.push(field_index);
-                indexed_fields.insert(field, field_index);
-
-                assigned = true;
-            }
-        }
-
-        if !assigned {
-            panic!("Could not assign any fields this loop!");
-        }
+                    .all(|v| is_valid_for_field(&v[*i], field))
+                    && is_valid_for_field(&my_ticket[*i], field)
+            })
+            .collect();
+        candidates.insert(field.name, indexes);
     }
 
-    println!("Assigned all fields: {:?}", indexed_fields);
+    let indexed_fields = match solve(&candidates, 2).into_unique() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    println!(
+        "Assigned all fields: {:?}",
+        indexed_fields
+            .iter()
+            .map(|(name, index)| (names.resolve(*name), index))
+            .collect::<HashMap<_, _>>()
+    );
 
     // Finally, the answer to our puzzle is the multiplication of all 'departure *' fields of our ticket
     let departure_fields = fields
         .iter()
-        .filter(|f| f.name.starts_with("departure "))
+        .filter(|f| names.resolve(f.name).starts_with("departure "))
         .collect::<Vec<_>>();
     if departure_fields.len() != 6 {
         panic!(
@@ -228,7 +161,7 @@ pub fn puzzle2() {
 
     let mut result = 1;
     for field in departure_fields {
-        if let Some(index) = indexed_fields.get(field) {
+        if let Some(index) = indexed_fields.get(&field.name) {
             result *= my_ticket[*index];
         } else {
             panic!(
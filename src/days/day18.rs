@@ -8,19 +8,32 @@ enum Operator {
     MULTIPLY,
 }
 
+fn apply(operator: Operator, left: u128, right: u128) -> Result<u128, String> {
+    match operator {
+        ADDITION => left.checked_add(right),
+        MULTIPLY => left.checked_mul(right),
+    }
+    .ok_or(format!(
+        "Overflow computing {} {:?} {}",
+        left, operator, right
+    ))
+}
+
 fn eval(expression: &str) -> Result<u128, String> {
     // The expression is a string containing numbers, operators, and parenthesis.
     // Contrary to normal, the evaluation order is parenthesis first, the left-to-right
     // e.g. 1 + 2 * 3 + 4 => 3 * 3 + 4 => 9 + 4 => 13
     // and  1 + (2 * 3) + 4 => 1 + 6 + 4 => 7 + 4 => 11
 
+    // Index on characters rather than bytes, so multi-byte input is rejected instead of sliced.
+    let chars = expression.chars().collect::<Vec<_>>();
     let mut result = 0;
 
     let mut operator = ADDITION;
     let mut index = 0;
 
-    while index < expression.len() {
-        match expression.chars().nth(index) {
+    while index < chars.len() {
+        match chars.get(index).copied() {
             Some('*') => {
                 operator = MULTIPLY;
                 index += 1
@@ -31,8 +44,8 @@ fn eval(expression: &str) -> Result<u128, String> {
             }
             Some(v) if v.is_digit(10) => {
                 // (Start of) a number, find all digit numbers and handle the operator
-                let num_str = expression
-                    .chars()
+                let num_str = chars
+                    .iter()
                     .skip(index)
                     .take_while(|c| c.is_digit(10))
                     .collect::<String>();
@@ -40,37 +53,35 @@ fn eval(expression: &str) -> Result<u128, String> {
                     .parse::<u128>()
                     .map_err(|pe| format!("Could not parse {} as number: {}", num_str, pe))?;
 
-                match operator {
-                    ADDITION => result += val,
-                    MULTIPLY => result *= val,
-                }
+                result = apply(operator, result, val)?;
 
                 index += num_str.len();
             }
             Some('(') => {
                 // Find the matching(!) closing brace, and evaluate the substring
                 let mut brace_level = 0;
-                let sub_expression = expression
-                    .chars()
+                let sub_expression = chars
+                    .iter()
                     .skip(index + 1)
-                    .take_while(|c| match c {
+                    .take_while(|c| match **c {
                         '(' => {
                             brace_level += 1;
                             true
                         }
                         ')' => {
+                            // Stop at the closing brace matching the one we started with
                             brace_level -= 1;
-                            brace_level == 0
+                            brace_level >= 0
                         }
                         _ => true,
                     })
                     .collect::<String>();
-                let val = eval(sub_expression.as_str())?;
-                match operator {
-                    ADDITION => result += val,
-                    MULTIPLY => result *= val,
+                if brace_level >= 0 {
+                    return Err(format!("Unmatched '(' at index {}", index));
                 }
-                index += sub_expression.len() + 2;
+                let val = eval(sub_expression.as_str())?;
+                result = apply(operator, result, val)?;
+                index += sub_expression.chars().count() + 2;
             }
             Some(v) if v.is_whitespace() => index += 1, // Skip whitespace
             Some(v) => {
@@ -113,10 +124,11 @@ enum Token {
 }
 
 fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars = expression.chars().collect::<Vec<_>>();
     let mut tokens = vec![];
     let mut index = 0;
-    while index < expression.len() {
-        match expression.chars().nth(index) {
+    while index < chars.len() {
+        match chars.get(index).copied() {
             Some('(') => {
                 tokens.push(ParOpen);
                 index += 1
@@ -134,8 +146,8 @@ fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
                 index += 1
             }
             Some(v) if v.is_digit(10) => {
-                let num_str = expression
-                    .chars()
+                let num_str = chars
+                    .iter()
                     .skip(index)
                     .take_while(|c| c.is_digit(10))
                     .collect::<String>();
@@ -156,15 +168,15 @@ fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
     Ok(tokens)
 }
 
-fn eval_tokens(tokens: &[Token]) -> u128 {
+fn eval_tokens(tokens: &[Token]) -> Result<u128, String> {
     // We'll first want to evaluate and replace the braced parts.
-    fn expand_braces(tokens: &[Token]) -> Vec<Token> {
+    fn expand_braces(tokens: &[Token]) -> Result<Vec<Token>, String> {
         let mut result = vec![];
         let mut par_start = 0;
         let mut par_level = 0;
 
-        for i in 0..tokens.len() {
-            match tokens[i] {
+        for (i, token) in tokens.iter().enumerate() {
+            match token {
                 ParOpen => {
                     par_level += 1;
                     if par_level == 1 {
@@ -172,102 +184,74 @@ fn eval_tokens(tokens: &[Token]) -> u128 {
                     }
                 }
                 ParClose => {
+                    if par_level == 0 {
+                        return Err(format!("Unmatched ')' at token {}", i));
+                    }
                     par_level -= 1;
                     if par_level == 0 {
-                        result.push(Lit(eval_tokens(&tokens[par_start..i])));
+                        result.push(Lit(eval_tokens(&tokens[par_start..i])?));
                     }
                 }
                 v if par_level == 0 => {
                     // Any token not in parenthesis is just kept:
-                    result.push(v);
+                    result.push(*v);
                 }
                 _ => { /* inside parenthesis, will be evaluated on ParClose */ }
             }
         }
 
-        result
-    }
-
-    // Then, without braces, we simply start looking for and replacing the additions!
-    fn compute_addition(tokens: &[Token]) -> Vec<Token> {
-        let mut result = vec![];
-
-        let mut previous = tokens[0];
-
-        let mut index = 1;
-        while index < tokens.len() {
-            match tokens[index] {
-                Op(ADDITION) => {
-                    // HANDLE!
-                    let left = match previous {
-                        Lit(v) => v,
-                        t => panic!("Expected a literal LHS, but got {:?}", t),
-                    };
-                    let right = match tokens[index + 1] {
-                        Lit(v) => v,
-                        t => panic!("Expected a literal RHS, but got {:?}", t),
-                    };
-                    previous = Lit(left + right);
-                    index += 2;
-                }
-                t @ Op(_) => {
-                    // Push the previous and this token on the result, set previous to the next token
-                    result.push(previous);
-                    result.push(t);
-                    previous = tokens[index + 1]; // Just panic if it's too far; should not happen.
-                    index += 2;
-                }
-                t => panic!("Expected to find only operations, but encountered {:?}", t),
-            }
+        if par_level != 0 {
+            return Err(format!("Unmatched '(' at token {}", par_start - 1));
         }
 
-        result.push(previous);
+        Ok(result)
+    }
 
-        result
+    fn literal(token: Option<&Token>) -> Result<u128, String> {
+        match token {
+            Some(Lit(v)) => Ok(*v),
+            t => Err(format!("Expected a literal, but got {:?}", t)),
+        }
     }
 
-    // Finally, find and replace the multiplications.
-    fn compute_multiplication(tokens: &[Token]) -> Vec<Token> {
+    // Then, without braces, we look for the given operator and replace it by its result.
+    // Since we do additions before multiplications, only multiplications may be left over.
+    fn compute(tokens: &[Token], operator: Operator) -> Result<Vec<Token>, String> {
         let mut result = vec![];
 
-        let mut previous = tokens[0];
+        let mut previous = literal(tokens.first())?;
 
         let mut index = 1;
         while index < tokens.len() {
+            let right = literal(tokens.get(index + 1))?;
             match tokens[index] {
-                Op(MULTIPLY) => {
+                Op(op) if op == operator => {
                     // HANDLE!
-                    let left = match previous {
-                        Lit(v) => v,
-                        t => panic!("Expected a literal LHS, but got {:?}", t),
-                    };
-                    let right = match tokens[index + 1] {
-                        Lit(v) => v,
-                        t => panic!("Expected a literal RHS, but got {:?}", t),
-                    };
-                    previous = Lit(left * right);
-                    index += 2;
+                    previous = apply(op, previous, right)?;
                 }
-                t => panic!(
-                    "Expected to find only MULTIPLY operands, but encountered {:?}",
-                    t
-                ),
+                t @ Op(MULTIPLY) => {
+                    // Push the previous and this token on the result, continue from the next token
+                    result.push(Lit(previous));
+                    result.push(t);
+                    previous = right;
+                }
+                t => return Err(format!("Expected an operator, but encountered {:?}", t)),
             }
+            index += 2;
         }
 
-        result.push(previous);
-
-        result
-    }
+        result.push(Lit(previous));
 
-    let result = compute_multiplication(&compute_addition(&expand_braces(tokens)));
-    if result.len() != 1 {
-        panic!("Expected a single literal result, but got: {:?}", result)
+        Ok(result)
     }
 
-    match result[0] {
-        Lit(v) => v,
-        t => panic!("Expected a single literal result, but got: {:?}", t),
+    let result = compute(&compute(&expand_braces(tokens)?, ADDITION)?, MULTIPLY)?;
+    match result[..] {
+        [Lit(v)] => Ok(v),
+        _ => Err(format!(
+            "Expected a single literal result, but got: {:?}",
+            result
+        )),
     }
 }
 
@@ -279,7 +263,7 @@ fn eval2(expression: &str) -> Result<u128, String> {
     // Tokenize the expression:
     let tokens = tokenize(expression)?;
     // Evaluate:
-    Ok(eval_tokens(&tokens))
+    eval_tokens(&tokens)
 }
 
 #[test]
@@ -289,6 +273,87 @@ fn test_eval2() {
     assert_eq!(eval2("(2 * 3) + (3 * 6)"), Ok(24));
 }
 
+#[test]
+fn test_eval_regressions() {
+    // Found by the fuzzer: braces nested three deep used to end the outer brace too early.
+    assert_eq!(eval("((2 * 3) + (4)) * 2"), Ok(20));
+    assert_eq!(eval2("((2 * 3) + (4)) * 2"), Ok(20));
+    assert_eq!(eval("(((2))) * 3"), Ok(6));
+    assert_eq!(eval2("(((2))) * 3"), Ok(6));
+    assert_eq!(eval("2 * (((1 + 2) * 3) + 4)"), Ok(26));
+    assert_eq!(eval2("2 * (((1 + 2) * 3) + 4)"), Ok(26));
+    // These used to panic:
+    assert!(eval("99999999999999999999 * 99999999999999999999").is_err());
+    assert!(eval2("99999999999999999999 * 99999999999999999999").is_err());
+    assert!(eval2("").is_err());
+    assert!(eval2("1 +").is_err());
+    assert!(eval2("+ 1").is_err());
+    assert!(eval2("1 )").is_err());
+    assert!(eval2("(1 + 2").is_err());
+    assert!(eval("(1 + 2").is_err());
+}
+
+#[test]
+fn test_tokenize_fuzz() {
+    use crate::util::fuzz::{check, Rng};
+
+    fn expression(rng: &mut Rng, depth: usize) -> Vec<Token> {
+        let mut tokens = vec![];
+        for i in 0..=rng.below(3) {
+            if i > 0 {
+                tokens.push(Op(*rng.pick(&[ADDITION, MULTIPLY])));
+            }
+            if depth > 0 && rng.chance(25) {
+                tokens.push(ParOpen);
+                tokens.append(&mut expression(rng, depth - 1));
+                tokens.push(ParClose);
+            } else {
+                tokens.push(Lit(rng.range(0, 999) as u128));
+            }
+        }
+        tokens
+    }
+
+    check(
+        "day 18 tokenize round-trip",
+        |rng| expression(rng, 3),
+        |tokens| {
+            let line = tokens
+                .iter()
+                .map(|t| match t {
+                    Lit(v) => v.to_string(),
+                    ParOpen => "(".to_owned(),
+                    ParClose => ")".to_owned(),
+                    Op(ADDITION) => "+".to_owned(),
+                    Op(MULTIPLY) => "*".to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let parsed = tokenize(&line)?;
+            if &parsed != tokens {
+                return Err(format!("'{}' tokenized as {:?}", line, parsed));
+            }
+            // Valid expressions should evaluate, whatever the result is.
+            eval(&line)?;
+            eval2(&line)?;
+            Ok(())
+        },
+    );
+
+    check(
+        "day 18 evaluation does not panic",
+        |rng| {
+            let alphabet = ['1', '9', '9', ' ', '+', '*', '(', ')', '\u{3000}'];
+            rng.string(&alphabet, 40)
+        },
+        |line| {
+            let _ = eval(line);
+            let _ = eval2(line);
+            Ok(())
+        },
+    );
+}
+
 pub fn puzzle2() {
     match read_raw_input(18)
         .and_then(|d| d.split("\n").map(eval2).collect::<Result<Vec<_>, _>>())
@@ -1,5 +1,6 @@
 use crate::days::day19::Rule::{Lit, Or, Ref, Seq};
 use crate::util::input::read_raw_input;
+use crate::util::sections::Sections;
 use std::collections::HashMap;
 use std::iter::FromIterator;
 
@@ -77,13 +78,64 @@ fn parse_rule(rule: &str) -> Result<(u32, Rule), String> {
     Ok((rule_id, Or(sequences)))
 }
 
-fn parse_rules(rules: &String) -> Result<HashMap<u32, Rule>, String> {
-    let rules = rules
-        .split("\n")
-        .map(parse_rule)
-        .collect::<Result<Vec<_>, _>>()?;
+#[test]
+fn test_parse_rule_fuzz() {
+    use crate::util::fuzz::check;
 
-    Ok(HashMap::from_iter(rules))
+    check(
+        "day 19 rule round-trip",
+        |rng| {
+            let id = rng.range(0, 200) as u32;
+            let rule = if rng.chance(20) {
+                Or(vec![Seq(vec![Lit(*rng.pick(&['a', 'b', 'x']))])])
+            } else {
+                Or((0..=rng.below(2))
+                    .map(|_| {
+                        Seq((0..=rng.below(3))
+                            .map(|_| Ref(rng.range(0, 200) as u32))
+                            .collect())
+                    })
+                    .collect())
+            };
+            (id, rule)
+        },
+        |(id, rule)| {
+            let sequences = match rule {
+                Or(sequences) => sequences
+                    .iter()
+                    .map(|s| match s {
+                        Seq(parts) => parts
+                            .iter()
+                            .map(|p| match p {
+                                Ref(r) => r.to_string(),
+                                Lit(c) => format!("\"{}\"", c),
+                                _ => unreachable!(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>(),
+                _ => unreachable!(),
+            };
+            let line = format!("{}: {}", id, sequences.join(" | "));
+
+            let parsed = parse_rule(&line)?;
+            if parsed != (*id, rule.clone()) {
+                return Err(format!("'{}' parsed as {:?}", line, parsed));
+            }
+            Ok(())
+        },
+    );
+
+    check(
+        "day 19 parser does not panic",
+        |rng| rng.noise(20),
+        |line| {
+            let _ = parse_rule(line);
+            Ok(())
+        },
+    );
 }
 
 fn matches(input: &str, rules: &HashMap<u32, Rule>) -> bool {
@@ -266,16 +318,23 @@ fn test_matches_with_loop_adv() {
     assert_eq!(matches("ab", &rules), false);
 }
 
+fn read_input() -> Result<(HashMap<u32, Rule>, Vec<String>), String> {
+    let data = read_raw_input(19)?;
+    // The rules, followed by a blank line and the messages to check.
+    let sections = Sections::split(&data).expect_count(2)?;
+
+    let rules = sections.at(0)?.parse_lines(parse_rule)?;
+    let lines = sections
+        .at(1)?
+        .lines()
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+    Ok((HashMap::from_iter(rules), lines))
+}
+
 pub fn puzzle1() {
-    let (rules, lines) = match read_raw_input(19).and_then(|d| {
-        let parts = d.split("\n\n").map(str::to_owned).collect::<Vec<_>>();
-        if parts.len() != 2 {
-            return Err(format!("Expected 2 parts of input, found {}", parts.len()));
-        }
-        let rules = parse_rules(&parts[0])?;
-        let lines = parts[1].split("\n").map(str::to_owned).collect::<Vec<_>>();
-        Ok((rules, lines))
-    }) {
+    let (rules, lines) = match read_input() {
         Err(e) => return eprintln!("{}", e),
         Ok(v) => v,
     };
@@ -291,15 +350,7 @@ pub fn puzzle1() {
 }
 
 pub fn puzzle2() {
-    let (mut rules, lines) = match read_raw_input(19).and_then(|d| {
-        let parts = d.split("\n\n").map(str::to_owned).collect::<Vec<_>>();
-        if parts.len() != 2 {
-            return Err(format!("Expected 2 parts of input, found {}", parts.len()));
-        }
-        let rules = parse_rules(&parts[0])?;
-        let lines = parts[1].split("\n").map(str::to_owned).collect::<Vec<_>>();
-        Ok((rules, lines))
-    }) {
+    let (mut rules, lines) = match read_input() {
         Err(e) => return eprintln!("{}", e),
         Ok(v) => v,
     };
@@ -78,12 +78,14 @@ fn parse_tile(data: &str) -> Result<Tile, String> {
     let pixel_map: [[Pixel; 10]; 10] = pixels
         .iter()
         .map(|l| {
-            let array: [Pixel; 10] = l[0..10].try_into().unwrap();
-            array
+            let array: [Pixel; 10] = l[..]
+                .try_into()
+                .map_err(|_| format!("Expected tile rows of 10 pixels, but got {}", l.len()))?;
+            Ok(array)
         })
-        .collect::<Vec<_>>()
+        .collect::<Result<Vec<_>, String>>()?
         .try_into()
-        .unwrap();
+        .unwrap(); // We checked for 10 rows above
 
     Ok(Tile {
         id: tile_id,
@@ -91,6 +93,62 @@ fn parse_tile(data: &str) -> Result<Tile, String> {
     })
 }
 
+#[test]
+fn test_parse_tile_fuzz() {
+    use crate::util::fuzz::{check, Rng};
+
+    fn tile(rng: &mut Rng) -> Tile {
+        let mut image = [[Black; 10]; 10];
+        for row in image.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel = *rng.pick(&[Black, White]);
+            }
+        }
+        Tile {
+            id: rng.range(1000, 9999) as u32,
+            image,
+        }
+    }
+
+    check("day 20 tile round-trip", tile, |tile| {
+        let parsed = parse_tile(&tile.to_string())?;
+        if &parsed != tile {
+            return Err(format!("parsed as {:?}", parsed));
+        }
+        Ok(())
+    });
+
+    check(
+        "day 20 parser does not panic",
+        |rng| {
+            // Start from a valid tile, and break it a bit
+            let mut data = tile(rng).to_string().chars().collect::<Vec<_>>();
+            for _ in 0..=rng.below(3) {
+                let index = rng.below(data.len());
+                if rng.chance(50) {
+                    data.remove(index);
+                } else {
+                    data.insert(index, rng.noise(1).chars().next().unwrap_or('#'));
+                }
+            }
+            data.into_iter().collect::<String>()
+        },
+        |data| {
+            let _ = parse_tile(data);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn test_parse_tile_regressions() {
+    // Rows that are too short or too long used to panic
+    let short_row = "Tile 1:\n".to_owned() + &["#########"; 10].join("\n");
+    assert!(parse_tile(&short_row).is_err());
+    let long_row = "Tile 1:\n".to_owned() + &["###########"; 10].join("\n");
+    assert!(parse_tile(&long_row).is_err());
+}
+
 fn read_tiles() -> Result<Vec<Tile>, String> {
     read_raw_input(20).and_then(|d| {
         d.split("\n\n")
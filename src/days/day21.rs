@@ -1,35 +1,35 @@
+use crate::util::assignment::solve;
 use crate::util::input::read_mapped_input;
-use std::collections::hash_map::RandomState;
+use crate::util::symbols::{Interner, Symbol};
 use std::collections::{HashMap, HashSet};
-use std::iter::FromIterator;
 
 #[derive(Eq, PartialEq, Debug)]
 struct Food {
-    ingredients: Vec<String>,
-    allergens: Vec<String>,
+    ingredients: HashSet<Symbol>,
+    allergens: HashSet<Symbol>,
 }
 
-fn parse_allergens(line: &str) -> Vec<String> {
+fn parse_allergens(line: &str, names: &mut Interner) -> HashSet<Symbol> {
     if line.len() == 0 {
-        return vec![];
+        return HashSet::new();
     }
 
     line["(contains ".len()..]
         .split(",")
         .map(str::trim)
-        .map(str::to_owned)
+        .map(|a| names.intern(a))
         .collect()
 }
 
-fn parse_food(line: String) -> Result<Food, String> {
+fn parse_food(line: String, names: &mut Interner) -> Result<Food, String> {
     let index = line.find("(").unwrap_or(line.len());
     let ingredients = line[0..index]
         .split(" ")
         .map(str::trim)
         .filter(|s| s.len() > 0)
-        .map(str::to_owned)
+        .map(|i| names.intern(i))
         .collect();
-    let allergens = parse_allergens(line[index..line.len() - 1].trim());
+    let allergens = parse_allergens(line[index..line.len() - 1].trim(), names);
 
     Ok(Food {
         ingredients,
@@ -37,72 +37,51 @@ fn parse_food(line: String) -> Result<Food, String> {
     })
 }
 
-fn read_input() -> Result<Vec<Food>, String> {
-    read_mapped_input(21, parse_food)
+fn read_input() -> Result<(Interner, Vec<Food>), String> {
+    let mut names = Interner::new();
+    let foods = read_mapped_input(21, |l| parse_food(l, &mut names))?;
+    Ok((names, foods))
 }
 
 pub fn puzzle1() {
-    let foods = match read_input() {
+    let (names, foods) = match read_input() {
         Err(e) => return eprintln!("{}", e),
         Ok(v) => v,
     };
 
-    let allergens: HashSet<String, RandomState> =
-        HashSet::from_iter(foods.iter().flat_map(|f| f.allergens.clone()));
-    let mut allergen_map: HashMap<String, String> = HashMap::new();
+    let allergens: HashSet<Symbol> = foods.iter().flat_map(|f| f.allergens.clone()).collect();
 
-    // For each allergen, find all ingredients that contain it; and deduce the unknown language translation for it.
-    // We loop here, in case we cannot deduce all uniquely in the first round
-    loop {
-        let allergens_to_map = &allergens
+    // For each allergen, the ingredients that could contain it are the ones found in every food
+    // listing that allergen. Deduce the unknown language translation from those options.
+    let mut candidates: HashMap<Symbol, HashSet<Symbol>> = HashMap::new();
+    for allergen in &allergens {
+        let food_with_allergen = foods
             .iter()
-            .filter(|a| allergen_map.get(*a).is_none())
+            .filter(|f| f.allergens.contains(allergen))
             .collect::<Vec<_>>();
-        if allergens_to_map.len() == 0 {
-            println!("Mapped all allergens to their translation!");
-            break;
-        }
 
-        println!("Looking for {} allergens", allergens_to_map.len());
-
-        let mut done_work = false;
-        for allergen in allergens_to_map {
-            let food_with_allergen = &foods
-                .iter()
-                .filter(|f| f.allergens.contains(&allergen))
-                .collect::<Vec<_>>();
-
-            let ingredients: HashSet<String, RandomState> = HashSet::from_iter(
-                food_with_allergen
-                    .iter()
-                    .flat_map(|f| f.ingredients.clone()),
-            );
-            let options = ingredients
-                .into_iter()
-                .filter(|i| !allergen_map.values().any(|v| v.eq(i)))
-                .filter(|i| food_with_allergen.iter().all(|f| f.ingredients.contains(i)))
-                .collect::<Vec<_>>();
-
-            if options.len() == 1 {
-                allergen_map.insert(allergen.to_string(), options[0].to_string());
-                done_work = true;
-                println!("Mapped {} to {}", allergen, options[0]);
-            } else {
-                println!(
-                    "Found {} options for {} ({:?})",
-                    options.len(),
-                    allergen,
-                    options
-                );
-            }
-        }
-
-        if !done_work {
-            panic!("Could not uniquely find ingredients any more, but we're also not done!");
-        }
+        let options = food_with_allergen[0]
+            .ingredients
+            .iter()
+            .filter(|i| food_with_allergen.iter().all(|f| f.ingredients.contains(i)))
+            .copied()
+            .collect();
+        candidates.insert(*allergen, options);
     }
 
-    println!("Map: {:?}", allergen_map);
+    let allergen_map = match solve(&candidates, 2).into_unique() {
+        Err(e) => return eprintln!("{}", e),
+        Ok(v) => v,
+    };
+
+    println!(
+        "Map: {:?}",
+        allergen_map
+            .iter()
+            .map(|(a, i)| (names.resolve(*a), names.resolve(*i)))
+            .collect::<HashMap<_, _>>()
+    );
+    let dangerous: HashSet<Symbol> = allergen_map.values().copied().collect();
 
     // Now that we have a translation map, finish the puzzle!
     // We need to count all ingredients in the list that are _not_ an allergen:
@@ -111,7 +90,7 @@ pub fn puzzle1() {
         .map(|f| {
             f.ingredients
                 .iter()
-                .filter(|i| !allergen_map.values().any(|a| a.eq(*i)))
+                .filter(|i| !dangerous.contains(i))
                 .count()
         })
         .sum::<usize>();
@@ -122,10 +101,10 @@ pub fn puzzle1() {
 
     // Sort allergens (map key) alphabetically, then map to values, and produce a comma-separated string
     let mut keys = allergen_map.keys().collect::<Vec<_>>();
-    keys.sort();
+    keys.sort_by_key(|k| names.resolve(**k));
     let result2 = keys
         .iter()
-        .map(|k| allergen_map.get(*k).unwrap().to_string())
+        .map(|k| names.resolve(allergen_map[*k]))
         .collect::<Vec<_>>()
         .join(",");
     println!(
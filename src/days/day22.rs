@@ -1,27 +1,17 @@
 use crate::util::input::read_raw_input;
+use crate::util::sections::Sections;
 use std::ops::AddAssign;
 
 fn read_decks() -> Result<(Vec<usize>, Vec<usize>), String> {
-    read_raw_input(22)
-        .and_then(|data| {
-            data.split("\n\n")
-                .map(|d| {
-                    // First line of the deck is just the 'player' line, so we ignore it!
-                    d.split("\n")
-                        .skip(1)
-                        .map(str::trim)
-                        .map(|c| c.parse::<usize>().map_err(|e| format!("{}", e)))
-                        .collect::<Result<Vec<_>, _>>()
-                })
-                .collect::<Result<Vec<Vec<_>>, _>>()
-        })
-        .and_then(|v| {
-            if v.len() == 2 {
-                Ok((v[0].clone(), v[1].clone()))
-            } else {
-                Err(format!("Expected 2 players but got {}", v.len()))
-            }
-        })
+    let data = read_raw_input(22)?;
+    // Each deck is headed by its 'Player N:' line
+    let sections = Sections::split(&data).expect_count(2)?;
+
+    let parse_card = |c: &str| c.trim().parse::<usize>().map_err(|e| format!("{}", e));
+    Ok((
+        sections.named("Player 1")?.parse_lines(parse_card)?,
+        sections.named("Player 2")?.parse_lines(parse_card)?,
+    ))
 }
 
 pub fn puzzle1() {
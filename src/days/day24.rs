@@ -129,6 +129,51 @@ fn test_to_location() {
     assert_eq!(to_location("nwwswee"), Ok(Location { x: 0, y: 0 }));
 }
 
+#[test]
+fn test_to_location_fuzz() {
+    use crate::util::fuzz::check;
+
+    let directions = [
+        ("e", -1, 0),
+        ("w", 1, 0),
+        ("ne", 0, -1),
+        ("nw", 1, -1),
+        ("se", -1, 1),
+        ("sw", 0, 1),
+    ];
+
+    check(
+        "day 24 directions round-trip",
+        |rng| {
+            (0..rng.below(30))
+                .map(|_| *rng.pick(&directions))
+                .collect::<Vec<_>>()
+        },
+        |steps| {
+            let line = steps.iter().map(|(d, _, _)| *d).collect::<String>();
+            let expected = Location {
+                x: steps.iter().map(|(_, dx, _)| dx).sum(),
+                y: steps.iter().map(|(_, _, dy)| dy).sum(),
+            };
+
+            let parsed = to_location(&line)?;
+            if parsed != expected {
+                return Err(format!("'{}' parsed as {:?}", line, parsed));
+            }
+            Ok(())
+        },
+    );
+
+    check(
+        "day 24 parser does not panic",
+        |rng| rng.noise(20),
+        |line| {
+            let _ = to_location(line);
+            Ok(())
+        },
+    );
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 enum Tile {
     Black,
@@ -1,5 +1,7 @@
 extern crate lazy_static;
 
+use crate::util::options::Options;
+use std::env;
 use std::io::{stdin, stdout, Write};
 
 mod days;
@@ -8,72 +10,130 @@ mod util;
 fn main() {
     let latest_day = 25;
 
-    let mut input = String::new();
-    print!(
-        "Please enter the day to run and press enter [{}]: ",
-        latest_day
-    );
-
-    if let Err(e) = stdout().flush() {
-        eprintln!("{}", e);
-        return;
-    }
-
-    match stdin().read_line(&mut input) {
+    let options = match Options::parse(env::args().skip(1)) {
         Err(e) => {
             eprintln!("{}", e);
             return;
         }
-        Ok(_) => {}
+        Ok(v) => v,
+    };
+
+    // The day can be passed as the first argument, otherwise we'll ask for it.
+    let mut input = options.positional().first().cloned().unwrap_or_default();
+    if input.is_empty() {
+        ask_day(latest_day, &mut input);
     }
 
-    let day = if input.trim().len() == 0 {
+    let day = if input.trim().is_empty() {
         Ok(latest_day)
     } else {
         input.trim().parse::<i32>()
     };
 
     match day {
+        Ok(1) if options.has("k") || options.has("target") || options.has("all") => {
+            days::day01::search(&options)
+        }
         Ok(1) => {
             days::day01::puzzle1();
             days::day01::puzzle2();
         }
+        Ok(2) if options.has("policies") => days::day02::report(&options),
         Ok(2) => {
             days::day02::puzzle1();
             days::day02::puzzle2();
         }
+        Ok(3)
+            if ["slopes", "render", "best", "max-dx", "max-dy"]
+                .iter()
+                .any(|o| options.has(o)) =>
+        {
+            days::day03::analyse(&options)
+        }
         Ok(3) => {
             days::day03::puzzle1();
             days::day03::puzzle2();
         }
+        Ok(4) if options.has("export") => days::day04::export(&options),
+        Ok(4)
+            if ["report", "schema", "unit", "output"]
+                .iter()
+                .any(|o| options.has(o)) =>
+        {
+            days::day04::report(&options)
+        }
         Ok(4) => {
             days::day04::puzzle1();
             days::day04::puzzle2();
         }
+        Ok(5)
+            if ["rows", "cols", "row-letters", "col-letters", "encode"]
+                .iter()
+                .any(|o| options.has(o)) =>
+        {
+            days::day05::codec(&options)
+        }
         Ok(5) => {
             days::day05::puzzle1();
             days::day05::puzzle2();
         }
+        Ok(6) if options.has("query") || options.has("histogram") => days::day06::query(&options),
         Ok(6) => {
             days::day06::puzzle1();
             days::day06::puzzle2();
         }
+        Ok(7) if options.has("validate") => days::day07::validate(),
+        Ok(7) if options.has("dot") => days::day07::export_dot(&options),
+        Ok(7) if options.has("bag") => days::day07::query(&options),
         Ok(7) => {
             days::day07::puzzle1();
             days::day07::puzzle2();
         }
+        Ok(8) if options.has("assemble") => days::day08::assemble_file(&options),
+        Ok(8) if options.has("disassemble") || options.has("optimise") => {
+            days::day08::disassemble_input(&options)
+        }
+        Ok(8) if options.has("trace") => days::day08::trace(&options),
+        Ok(8) if options.has("debug") => days::day08::debug(),
         Ok(8) => {
             days::day08::puzzle1();
             days::day08::puzzle2();
         }
+        Ok(9) if options.has("target") || options.has("min-len") => {
+            days::day09::weaknesses(&options)
+        }
+        Ok(9) if options.has("preamble") => days::day09::validate(&options),
         Ok(9) => {
             days::day09::puzzle1();
             days::day09::puzzle2();
         }
+        Ok(10)
+            if ["list", "limit", "nth", "sample", "seed"]
+                .iter()
+                .any(|o| options.has(o)) =>
+        {
+            days::day10::arrangements(&options)
+        }
+        Ok(10) if options.has("max-gap") || options.has("offset") => days::day10::analyse(&options),
         Ok(10) => {
             days::day10::puzzle1();
             days::day10::puzzle2();
         }
+        Ok(11)
+            if options.has("rules")
+                || options.has("neighbours")
+                || options.has("occupy")
+                || options.has("vacate")
+                || options.has("animate")
+                || options.has("frames")
+                || options.has("stats")
+                || options.has("max-rounds")
+                || options.has("delay")
+                || options.has("format")
+                || options.has("scale") =>
+        {
+            days::day11::simulate(&options)
+        }
         Ok(11) => {
             days::day11::puzzle1();
             days::day11::puzzle2();
@@ -138,3 +198,19 @@ fn main() {
         Err(e) => eprintln!("{}", e),
     }
 }
+
+fn ask_day(latest_day: i32, input: &mut String) {
+    print!(
+        "Please enter the day to run and press enter [{}]: ",
+        latest_day
+    );
+
+    if let Err(e) = stdout().flush() {
+        eprintln!("{}", e);
+        return;
+    }
+
+    if let Err(e) = stdin().read_line(input) {
+        eprintln!("{}", e);
+    }
+}
@@ -1 +1,10 @@
+pub mod assignment;
+pub mod bignum;
+#[cfg(test)]
+pub mod fuzz;
 pub mod input;
+pub mod options;
+pub mod parse;
+pub mod random;
+pub mod sections;
+pub mod symbols;
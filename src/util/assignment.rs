@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// Solves "which key maps to which value" puzzles: every key has a set of candidate values, and
+// every key needs to end up with a unique value. We first propagate (a key with a single option
+// claims it, removing it from all other keys), and only fall back to a maximum bipartite matching
+// (to prove there is a solution at all) and backtracking (to find them) when propagation stalls.
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum Solution<K: Eq + Hash, V> {
+    Unique(HashMap<K, V>),
+    Ambiguous(Vec<HashMap<K, V>>),
+    Unsatisfiable(String),
+}
+
+impl<K: Eq + Hash + Debug, V: Eq + Debug> Solution<K, V> {
+    pub fn into_unique(self) -> Result<HashMap<K, V>, String> {
+        match self {
+            Solution::Unique(v) => Ok(v),
+            Solution::Unsatisfiable(e) => Err(format!("No valid assignment exists: {}", e)),
+            Solution::Ambiguous(solutions) => {
+                // Report the keys that do not have the same value in every solution we found.
+                let mut undecided = solutions[0]
+                    .iter()
+                    .filter(|(k, v)| solutions.iter().any(|s| s.get(*k) != Some(*v)))
+                    .map(|(k, _)| format!("{:?}", k))
+                    .collect::<Vec<_>>();
+                undecided.sort();
+                Err(format!(
+                    "Found at least {} valid assignments; undecided keys: {}",
+                    solutions.len(),
+                    undecided.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+type Domains<K, V> = Vec<(K, HashSet<V>)>;
+
+// Fixes keys with a single option, until nothing changes any more.
+// Returns an error when a key runs out of options.
+fn propagate<K: Debug, V: Eq + Hash + Clone + Debug>(
+    domains: &mut Domains<K, V>,
+) -> Result<(), String> {
+    let mut claimed: HashSet<V> = HashSet::new();
+
+    loop {
+        let mut changed = false;
+
+        for i in 0..domains.len() {
+            if domains[i].1.len() != 1 {
+                continue;
+            }
+
+            let value = domains[i].1.iter().next().unwrap().clone();
+            if !claimed.insert(value.clone()) {
+                continue;
+            }
+
+            for (j, (key, options)) in domains.iter_mut().enumerate() {
+                if j == i || !options.remove(&value) {
+                    continue;
+                }
+
+                if options.is_empty() {
+                    return Err(format!(
+                        "{:?} has no options left after {:?} was claimed",
+                        key, value
+                    ));
+                }
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return Ok(());
+        }
+    }
+}
+
+// Kuhn's augmenting path algorithm; returns, per key index, the value it got matched with.
+pub fn maximum_matching<V: Eq + Hash + Clone>(options: &[&HashSet<V>]) -> Vec<Option<V>> {
+    fn augment<V: Eq + Hash + Clone>(
+        key: usize,
+        options: &[&HashSet<V>],
+        owner: &mut HashMap<V, usize>,
+        visited: &mut HashSet<V>,
+    ) -> bool {
+        for value in options[key].iter() {
+            if !visited.insert(value.clone()) {
+                continue;
+            }
+
+            let free = match owner.get(value) {
+                None => true,
+                Some(&other) => augment(other, options, owner, visited),
+            };
+            if free {
+                owner.insert(value.clone(), key);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    let mut owner: HashMap<V, usize> = HashMap::new();
+    for key in 0..options.len() {
+        augment(key, options, &mut owner, &mut HashSet::new());
+    }
+
+    let mut result = vec![None; options.len()];
+    for (value, key) in owner {
+        result[key] = Some(value);
+    }
+    result
+}
+
+fn backtrack<K: Clone + Debug, V: Eq + Hash + Clone + Debug>(
+    domains: Domains<K, V>,
+    limit: usize,
+    solutions: &mut Vec<Domains<K, V>>,
+) {
+    let mut domains = domains;
+    if solutions.len() >= limit || propagate(&mut domains).is_err() {
+        return;
+    }
+
+    let open = domains
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, options))| options.len() > 1)
+        .min_by_key(|(_, (_, options))| options.len())
+        .map(|(i, _)| i);
+
+    let index = match open {
+        None => {
+            // Propagation only leaves singletons once every key claimed a different value.
+            solutions.push(domains);
+            return;
+        }
+        Some(i) => i,
+    };
+
+    for value in domains[index].1.iter() {
+        let mut attempt = domains.clone();
+        attempt[index].1 = [value.clone()].iter().cloned().collect();
+        backtrack(attempt, limit, solutions);
+    }
+}
+
+// Finds up to `limit` assignments of keys to distinct values, where each key takes one of its
+// candidate values.
+pub fn solve<K, V>(candidates: &HashMap<K, HashSet<V>>, limit: usize) -> Solution<K, V>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Eq + Hash + Clone + Debug,
+{
+    let mut domains: Domains<K, V> = candidates
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    if let Some((key, _)) = domains.iter().find(|(_, options)| options.is_empty()) {
+        return Solution::Unsatisfiable(format!("{:?} does not have any options", key));
+    }
+
+    if let Err(e) = propagate(&mut domains) {
+        return Solution::Unsatisfiable(e);
+    }
+
+    let matching = maximum_matching(&domains.iter().map(|(_, o)| o).collect::<Vec<_>>());
+    let mut unmatched = domains
+        .iter()
+        .zip(matching.iter())
+        .filter(|(_, m)| m.is_none())
+        .map(|((k, _), _)| format!("{:?}", k))
+        .collect::<Vec<_>>();
+    if !unmatched.is_empty() {
+        unmatched.sort();
+        return Solution::Unsatisfiable(format!(
+            "only {} of {} keys can get a distinct value, could not place: {}",
+            domains.len() - unmatched.len(),
+            domains.len(),
+            unmatched.join(", ")
+        ));
+    }
+
+    let mut found = vec![];
+    backtrack(domains, limit.max(1), &mut found);
+
+    let mut solutions = found
+        .into_iter()
+        .map(|d| {
+            d.into_iter()
+                .map(|(k, v)| (k, v.into_iter().next().unwrap()))
+                .collect::<HashMap<_, _>>()
+        })
+        .collect::<Vec<_>>();
+
+    match solutions.len() {
+        // The matching above guarantees a solution, so this should not happen.
+        0 => Solution::Unsatisfiable("no assignment found while backtracking".to_owned()),
+        1 => Solution::Unique(solutions.remove(0)),
+        _ => Solution::Ambiguous(solutions),
+    }
+}
+
+#[cfg(test)]
+fn to_candidates(input: &[(&'static str, &[u32])]) -> HashMap<&'static str, HashSet<u32>> {
+    input
+        .iter()
+        .map(|(k, v)| (*k, v.iter().cloned().collect()))
+        .collect()
+}
+
+#[test]
+fn test_solve_by_propagation() {
+    let candidates = to_candidates(&[("a", &[1, 2, 3]), ("b", &[2]), ("c", &[2, 3])]);
+
+    let result = solve(&candidates, 10).into_unique().unwrap();
+    assert_eq!(result.get("a"), Some(&1));
+    assert_eq!(result.get("b"), Some(&2));
+    assert_eq!(result.get("c"), Some(&3));
+}
+
+#[test]
+fn test_solve_needs_backtracking() {
+    // No key has a single option, but a and b need both 1 and 2; leaving 3 for c and 4 for d.
+    let candidates = to_candidates(&[
+        ("a", &[1, 2]),
+        ("b", &[1, 2]),
+        ("c", &[2, 3]),
+        ("d", &[3, 4]),
+    ]);
+
+    match solve(&candidates, 10) {
+        Solution::Ambiguous(solutions) => {
+            assert_eq!(solutions.len(), 2);
+            assert!(solutions.iter().all(|s| s.get("c") == Some(&3)));
+            assert!(solutions.iter().all(|s| s.get("d") == Some(&4)));
+        }
+        other => panic!("Expected an ambiguous result, but got {:?}", other),
+    }
+    assert_eq!(
+        solve(&candidates, 10).into_unique(),
+        Err("Found at least 2 valid assignments; undecided keys: \"a\", \"b\"".to_owned())
+    );
+
+    let candidates = to_candidates(&[("a", &[1]), ("b", &[1, 2]), ("c", &[2, 3]), ("d", &[3, 4])]);
+    let result = solve(&candidates, 10).into_unique().unwrap();
+    assert_eq!(result.get("c"), Some(&3));
+    assert_eq!(result.get("d"), Some(&4));
+}
+
+#[test]
+fn test_solve_limit() {
+    let candidates = to_candidates(&[("a", &[1, 2, 3]), ("b", &[1, 2, 3]), ("c", &[1, 2, 3])]);
+
+    match solve(&candidates, 2) {
+        Solution::Ambiguous(solutions) => assert_eq!(solutions.len(), 2),
+        other => panic!("Expected an ambiguous result, but got {:?}", other),
+    }
+}
+
+#[test]
+fn test_solve_unsatisfiable() {
+    let candidates = to_candidates(&[("a", &[1]), ("b", &[1])]);
+    match solve(&candidates, 10) {
+        Solution::Unsatisfiable(e) => {
+            assert!(e.ends_with("has no options left after 1 was claimed"))
+        }
+        other => panic!("Expected an unsatisfiable result, but got {:?}", other),
+    }
+
+    // Propagation can't find anything wrong here, but three keys can't share two values.
+    let candidates = to_candidates(&[("a", &[1, 2]), ("b", &[1, 2]), ("c", &[1, 2]), ("d", &[3])]);
+    match solve(&candidates, 10) {
+        Solution::Unsatisfiable(e) => assert!(e.starts_with("only 3 of 4 keys")),
+        other => panic!("Expected an unsatisfiable result, but got {:?}", other),
+    }
+}
+
+#[test]
+fn test_maximum_matching() {
+    let a: HashSet<u32> = [1, 2].iter().cloned().collect();
+    let b: HashSet<u32> = [1].iter().cloned().collect();
+    let c: HashSet<u32> = [2, 3].iter().cloned().collect();
+
+    // Greedy would give a=1 and b nothing; augmenting moves a to 2 and c to 3.
+    let result = maximum_matching(&[&a, &b, &c]);
+    assert!(result.iter().all(Option::is_some));
+    assert_eq!(result[1], Some(1));
+}
@@ -0,0 +1,196 @@
+use crate::util::random::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// Unsigned integers that don't overflow; for counts that grow exponentially with the input (like
+// the number of adapter arrangements). Only has the operations we need.
+
+const BASE: u64 = 1_000_000_000;
+
+// Little-endian limbs in base 10^9, which makes printing easy. There are never any trailing zero
+// limbs, so zero has no limbs at all.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> BigUint {
+        BigUint::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn add(&self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = carry
+                + *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64;
+            limbs.push((sum % BASE) as u32);
+            carry = sum / BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+
+        BigUint { limbs }
+    }
+
+    // self - other, or None if that would go below zero.
+    pub fn checked_sub(&self, other: &BigUint) -> Option<BigUint> {
+        if self < other {
+            return None;
+        }
+
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            let subtract = borrow + *other.limbs.get(i).unwrap_or(&0) as u64;
+            let limb = limb as u64;
+            if limb >= subtract {
+                limbs.push((limb - subtract) as u32);
+                borrow = 0;
+            } else {
+                limbs.push((limb + BASE - subtract) as u32);
+                borrow = 1;
+            }
+        }
+
+        Some(BigUint::trimmed(limbs))
+    }
+
+    // A uniformly random number in 0..self (which should not be zero). Picks random limbs below
+    // the top limb + 1, and tries again when that ends up too high; which happens less than half
+    // of the time.
+    pub fn random_below(&self, rng: &mut Rng) -> BigUint {
+        let (&top, rest) = self.limbs.split_last().expect("No numbers below zero");
+        loop {
+            let mut limbs = rest
+                .iter()
+                .map(|_| rng.below(BASE as usize) as u32)
+                .collect::<Vec<_>>();
+            limbs.push(rng.below(top as usize + 1) as u32);
+
+            let value = BigUint::trimmed(limbs);
+            if value < *self {
+                return value;
+            }
+        }
+    }
+
+    fn trimmed(mut limbs: Vec<u32>) -> BigUint {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut value: u64) -> BigUint {
+        let mut limbs = vec![];
+        while value > 0 {
+            limbs.push((value % BASE) as u32);
+            value /= BASE;
+        }
+        BigUint { limbs }
+    }
+}
+
+impl FromStr for BigUint {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<BigUint, String> {
+        if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("'{}' is not a non-negative whole number", value));
+        }
+
+        // Chunks of 9 digits, starting from the least significant end. All digits are ASCII, so
+        // slicing on bytes is safe.
+        let limbs = (0..value.len())
+            .rev()
+            .step_by(9)
+            .map(|end| {
+                let start = end.saturating_sub(8);
+                value[start..=end].parse::<u32>().unwrap()
+            })
+            .collect();
+        Ok(BigUint::trimmed(limbs))
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &BigUint) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &BigUint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limbs.split_last() {
+            None => write!(f, "0"),
+            Some((last, rest)) => {
+                write!(f, "{}", last)?;
+                for limb in rest.iter().rev() {
+                    write!(f, "{:09}", limb)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_big_uint() {
+    let max = BigUint::from(u64::MAX);
+    assert_eq!(max.to_string(), "18446744073709551615");
+
+    let double = max.add(&max);
+    assert_eq!(double.to_string(), "36893488147419103230");
+    assert!(double > max);
+    assert!(max < double);
+
+    assert_eq!(BigUint::zero().to_string(), "0");
+    assert!(BigUint::from(0).is_zero());
+    assert_eq!(
+        BigUint::from(999_999_999)
+            .add(&BigUint::from(1))
+            .to_string(),
+        "1000000000"
+    );
+
+    let big = "123456789012345678901234567890".parse::<BigUint>().unwrap();
+    assert_eq!(big.to_string(), "123456789012345678901234567890");
+    assert_eq!("000".parse::<BigUint>(), Ok(BigUint::zero()));
+    assert!("-1".parse::<BigUint>().is_err());
+    assert!("".parse::<BigUint>().is_err());
+
+    assert_eq!(
+        double.checked_sub(&max).map(|v| v.to_string()),
+        Some(max.to_string())
+    );
+    assert_eq!(
+        BigUint::from(1_000_000_000).checked_sub(&BigUint::from(1)),
+        Some(BigUint::from(999_999_999))
+    );
+    assert_eq!(max.checked_sub(&max), Some(BigUint::zero()));
+    assert_eq!(max.checked_sub(&double), None);
+
+    let mut rng = Rng::new(2020);
+    assert!((0..100).all(|_| big.random_below(&mut rng) < big));
+    assert!((0..10).all(|_| BigUint::from(1).random_below(&mut rng).is_zero()));
+}
@@ -0,0 +1,113 @@
+use std::env;
+use std::fmt::Debug;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+// A tiny randomized test harness for our parsers. Each case gets its own seed, derived from a base
+// seed; a failure reports the case seed, so it can be reproduced by running the tests with
+// FUZZ_SEED=<seed> FUZZ_CASES=1. Any crash found should be added to the parser's regression test.
+
+pub use crate::util::random::Rng;
+
+// Helpers for generating test input.
+impl Rng {
+    // A number in min..=max
+    pub fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + (self.next_u64() % ((max - min) as u64 + 1)) as i64
+    }
+
+    // True in `percent` out of 100 calls.
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    pub fn string(&mut self, alphabet: &[char], max_len: usize) -> String {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| *self.pick(alphabet)).collect()
+    }
+
+    // Random garbage, biased towards characters our puzzle inputs use, but including multi-byte
+    // characters to catch slicing strings on byte indexes.
+    pub fn noise(&mut self, max_len: usize) -> String {
+        self.string(NOISE, max_len)
+    }
+}
+
+const NOISE: &[char] = &[
+    'a', 'b', 'e', 'n', 's', 'w', 'z', 'A', 'X', '0', '1', '7', '9', ' ', ' ', '\n', ':', ',', '.',
+    '-', '#', '(', ')', '+', '*', '|', '"', 'é', '€', '\u{3000}',
+];
+
+fn env_or(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// Generates inputs and checks the property holds for every one of them; a property fails by
+// returning an error or by panicking.
+pub fn check<T, G, P>(name: &str, mut generate: G, mut property: P)
+where
+    T: Debug,
+    G: FnMut(&mut Rng) -> T,
+    P: FnMut(&T) -> Result<(), String>,
+{
+    let seed = env_or("FUZZ_SEED", 2020);
+    let cases = env_or("FUZZ_CASES", 500);
+
+    for case in 0..cases {
+        let case_seed = seed.wrapping_add(case);
+        let input = generate(&mut Rng::new(case_seed));
+
+        let outcome = match catch_unwind(AssertUnwindSafe(|| property(&input))) {
+            Ok(result) => result,
+            Err(panic) => Err(match panic.downcast_ref::<String>() {
+                Some(message) => format!("panicked: {}", message),
+                None => match panic.downcast_ref::<&str>() {
+                    Some(message) => format!("panicked: {}", message),
+                    None => "panicked".to_owned(),
+                },
+            }),
+        };
+
+        if let Err(e) = outcome {
+            panic!(
+                "Property '{}' failed (FUZZ_SEED={}) for input {:?}: {}",
+                name, case_seed, input, e
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rng_helpers() {
+    let mut rng = Rng::new(7);
+    assert!((0..100)
+        .map(|_| rng.range(-2, 2))
+        .all(|v| (-2..=2).contains(&v)));
+    assert!((0..100).all(|_| rng.string(&['a'], 3).len() <= 3));
+}
+
+#[test]
+fn test_check_reports_failures() {
+    let result = catch_unwind(|| {
+        check(
+            "small",
+            |rng| rng.below(10),
+            |v| {
+                if *v == 3 {
+                    panic!("found a three")
+                }
+                Ok(())
+            },
+        )
+    });
+    let message = result.unwrap_err();
+    let message = message.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("Property 'small' failed (FUZZ_SEED="));
+    assert!(message.ends_with("for input 3: panicked: found a three"));
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+// Command line options for the runner: positional values (the day to run), and `--name value`,
+// `--name=value` or `--flag` options that some days use to run something else than the puzzle.
+// Options can be given more than once, but only where `get_all` reads them.
+
+#[derive(Eq, PartialEq, Debug, Default)]
+pub struct Options {
+    positional: Vec<String>,
+    named: HashMap<String, Vec<Option<String>>>,
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                None => {
+                    options.positional.push(arg);
+                    continue;
+                }
+                Some(n) => n,
+            };
+
+            let (name, value) = match name.find('=') {
+                Some(i) => (name[..i].to_owned(), Some(name[i + 1..].to_owned())),
+                None => match args.peek() {
+                    Some(next) if !next.starts_with("--") => (name.to_owned(), args.next()),
+                    _ => (name.to_owned(), None),
+                },
+            };
+
+            if name.is_empty() {
+                return Err(format!("Invalid option '{}'", arg));
+            }
+            options.named.entry(name).or_default().push(value);
+        }
+
+        Ok(options)
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.named.contains_key(name)
+    }
+
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, String>
+    where
+        T::Err: Display,
+    {
+        match self.named.get(name).map(|v| v.as_slice()) {
+            None => Ok(None),
+            Some([value]) => Options::convert(name, value).map(Some),
+            Some(_) => Err(format!("Option --{} was given more than once", name)),
+        }
+    }
+
+    // All values of an option that can be given more than once, in order.
+    pub fn get_all<T: FromStr>(&self, name: &str) -> Result<Vec<T>, String>
+    where
+        T::Err: Display,
+    {
+        self.named
+            .get(name)
+            .map(|values| values.iter().map(|v| Options::convert(name, v)).collect())
+            .unwrap_or_else(|| Ok(vec![]))
+    }
+
+    fn convert<T: FromStr>(name: &str, value: &Option<String>) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        match value {
+            None => Err(format!("Option --{} needs a value", name)),
+            Some(v) => v
+                .parse::<T>()
+                .map_err(|e| format!("Invalid value '{}' for --{}: {}", v, name, e)),
+        }
+    }
+
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        self.get(name).map(|v| v.unwrap_or(default))
+    }
+}
+
+#[cfg(test)]
+fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn test_parse_options() {
+    let options = Options::parse(to_args(&["1", "--k", "3", "--target=-20", "--all"])).unwrap();
+
+    assert_eq!(options.positional(), &["1".to_owned()]);
+    assert_eq!(options.get::<usize>("k"), Ok(Some(3)));
+    assert_eq!(options.get::<i32>("target"), Ok(Some(-20)));
+    assert_eq!(options.get_or::<i32>("missing", 2020), Ok(2020));
+    assert!(options.has("all"));
+    assert!(!options.has("missing"));
+    assert_eq!(
+        options.get::<i32>("all"),
+        Err("Option --all needs a value".to_owned())
+    );
+    assert_eq!(
+        Options::parse(to_args(&["--k", "x"]))
+            .unwrap()
+            .get::<usize>("k"),
+        Err("Invalid value 'x' for --k: invalid digit found in string".to_owned())
+    );
+    let repeated = Options::parse(to_args(&["--k", "1", "--k", "2", "--all"])).unwrap();
+    assert_eq!(
+        repeated.get::<usize>("k"),
+        Err("Option --k was given more than once".to_owned())
+    );
+    assert_eq!(repeated.get_all::<usize>("k"), Ok(vec![1, 2]));
+    assert_eq!(repeated.get_all::<usize>("missing"), Ok(vec![]));
+    assert!(repeated.get_all::<usize>("all").is_err());
+}
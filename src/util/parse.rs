@@ -0,0 +1,171 @@
+use regex::Regex;
+use std::any::type_name;
+use std::fmt::Display;
+use std::str::FromStr;
+
+// A regex for a single line of input, of which the capture groups get parsed into typed values.
+// Patterns are meant to be compiled once, by putting them in a `lazy_static!` block:
+//
+// lazy_static! {
+//     static ref ENTRY: LinePattern = LinePattern::new(r"^(\d+)-(\d+) ([a-z])$");
+// }
+// let (min, max, letter): (i32, i32, char) = ENTRY.parse(&line)?;
+
+pub struct LinePattern {
+    regex: Regex,
+}
+
+pub struct Captures<'a> {
+    line: &'a str,
+    captures: regex::Captures<'a>,
+}
+
+pub trait FromCaptures: Sized {
+    fn from_captures(captures: &Captures) -> Result<Self, String>;
+}
+
+impl LinePattern {
+    pub fn new(pattern: &str) -> LinePattern {
+        // Patterns are constants in our code, an invalid one is a bug rather than an input error.
+        LinePattern {
+            regex: Regex::new(pattern).unwrap_or_else(|e| panic!("Invalid pattern: {}", e)),
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        self.regex.is_match(line)
+    }
+
+    pub fn captures<'a>(&self, line: &'a str) -> Result<Captures<'a>, String> {
+        self.regex
+            .captures(line)
+            .map(|captures| Captures { line, captures })
+            .ok_or_else(|| {
+                format!(
+                    "Line '{}' does not match the expected format /{}/",
+                    line,
+                    self.regex.as_str()
+                )
+            })
+    }
+
+    pub fn parse<T: FromCaptures>(&self, line: &str) -> Result<T, String> {
+        T::from_captures(&self.captures(line)?)
+    }
+}
+
+impl<'a> Captures<'a> {
+    fn convert<T: FromStr>(&self, group: String, value: Option<&'a str>) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        let value = value.ok_or_else(|| {
+            format!(
+                "Group {} did not capture anything in '{}'",
+                group, self.line
+            )
+        })?;
+
+        value.parse::<T>().map_err(|e| {
+            format!(
+                "Could not read group {} ('{}') in '{}' as {}: {}",
+                group,
+                value,
+                self.line,
+                type_name::<T>(),
+                e
+            )
+        })
+    }
+
+    // Parses a positional capture group; 1 is the first group.
+    pub fn at<T: FromStr>(&self, index: usize) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        self.convert(
+            format!("{}", index),
+            self.captures.get(index).map(|m| m.as_str()),
+        )
+    }
+
+    // Parses a named capture group.
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        self.convert(
+            format!("'{}'", name),
+            self.captures.name(name).map(|m| m.as_str()),
+        )
+    }
+}
+
+// Tuples are filled from the positional capture groups, in order.
+macro_rules! tuple_from_captures {
+    ($($t:ident => $i:expr),+) => {
+        impl<$($t: FromStr),+> FromCaptures for ($($t,)+)
+        where
+            $($t::Err: Display),+
+        {
+            fn from_captures(captures: &Captures) -> Result<Self, String> {
+                Ok(($(captures.at::<$t>($i)?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_captures!(A => 1);
+tuple_from_captures!(A => 1, B => 2);
+tuple_from_captures!(A => 1, B => 2, C => 3);
+tuple_from_captures!(A => 1, B => 2, C => 3, D => 4);
+tuple_from_captures!(A => 1, B => 2, C => 3, D => 4, E => 5);
+
+#[test]
+fn test_parse_tuple() {
+    let pattern = LinePattern::new(r"^(\d+)-(\d+) ([a-z]): ([a-z]+)$");
+
+    assert_eq!(
+        pattern.parse::<(i32, i32, char, String)>("1-3 a: abcde"),
+        Ok((1, 3, 'a', "abcde".to_owned()))
+    );
+    assert_eq!(
+        pattern.parse::<(i32, i32, char, String)>("1-3 a abcde"),
+        Err(r"Line '1-3 a abcde' does not match the expected format /^(\d+)-(\d+) ([a-z]): ([a-z]+)$/".to_owned())
+    );
+    assert_eq!(
+        pattern.parse::<(u8, i32, char, String)>("1000-3 a: abcde"),
+        Err("Could not read group 1 ('1000') in '1000-3 a: abcde' as u8: number too large to fit in target type".to_owned())
+    );
+}
+
+#[test]
+fn test_parse_struct() {
+    #[derive(Eq, PartialEq, Debug)]
+    struct Write {
+        address: u64,
+        value: u64,
+    }
+
+    impl FromCaptures for Write {
+        fn from_captures(captures: &Captures) -> Result<Self, String> {
+            Ok(Write {
+                address: captures.get("address")?,
+                value: captures.get("value")?,
+            })
+        }
+    }
+
+    let pattern = LinePattern::new(r"^mem\[(?P<address>\d+)\] = (?P<value>\d+)?$");
+    assert_eq!(
+        pattern.parse("mem[8] = 11"),
+        Ok(Write {
+            address: 8,
+            value: 11
+        })
+    );
+    assert_eq!(
+        pattern.parse::<Write>("mem[8] = "),
+        Err("Group 'value' did not capture anything in 'mem[8] = '".to_owned())
+    );
+}
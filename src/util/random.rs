@@ -0,0 +1,43 @@
+// A small seedable random number generator, so results can be reproduced from a seed without
+// any dependencies. SplitMix64; good enough for tests and sampling, not for anything secret.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // A number in 0..n, without the bias of taking a plain modulo.
+    pub fn below(&mut self, n: usize) -> usize {
+        let n = n as u64;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return (value % n) as usize;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rng_is_seedable() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    assert_eq!(
+        (0..10).map(|_| a.next_u64()).collect::<Vec<_>>(),
+        (0..10).map(|_| b.next_u64()).collect::<Vec<_>>()
+    );
+
+    let mut rng = Rng::new(7);
+    assert!((0..100).all(|_| rng.below(3) < 3));
+    assert_eq!(rng.below(1), 0);
+}
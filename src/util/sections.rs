@@ -0,0 +1,206 @@
+// Some puzzle inputs consist of multiple parts, separated by blank lines and/or started by a
+// header line (e.g. "your ticket:" or "Player 1:"). This splits such input into sections, and
+// makes sure parse errors tell us which section and line they came from.
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Section<'a> {
+    index: usize,
+    header: Option<&'a str>,
+    // 1-based line number (in the full input) of the first line in `lines`.
+    first_line: usize,
+    lines: Vec<&'a str>,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Sections<'a> {
+    sections: Vec<Section<'a>>,
+}
+
+fn is_header(line: &str) -> bool {
+    line.ends_with(':')
+}
+
+impl<'a> Section<'a> {
+    pub fn name(&self) -> String {
+        match self.header {
+            Some(h) => format!("'{}'", h),
+            None => format!("#{}", self.index + 1),
+        }
+    }
+
+    pub fn lines(&self) -> &[&'a str] {
+        &self.lines
+    }
+
+    fn error_at(&self, offset: usize, error: String) -> String {
+        format!(
+            "Section {}, line {}: {}",
+            self.name(),
+            self.first_line + offset,
+            error
+        )
+    }
+
+    // Parses every line of the section separately.
+    pub fn parse_lines<T, F: FnMut(&'a str) -> Result<T, String>>(
+        &self,
+        mut parser: F,
+    ) -> Result<Vec<T>, String> {
+        self.lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| parser(line).map_err(|e| self.error_at(i, e)))
+            .collect()
+    }
+
+    // Parses a section that should consist of exactly one line.
+    pub fn parse_single<T, F: FnOnce(&'a str) -> Result<T, String>>(
+        &self,
+        parser: F,
+    ) -> Result<T, String> {
+        if self.lines.len() != 1 {
+            return Err(self.error_at(
+                0,
+                format!("Expected a single line, but got {}", self.lines.len()),
+            ));
+        }
+
+        parser(self.lines[0]).map_err(|e| self.error_at(0, e))
+    }
+}
+
+impl<'a> Sections<'a> {
+    // Splits on blank lines. A line ending in ':' is a header; it starts a new section (even
+    // without a blank line in front of it) and is used as that section's name.
+    pub fn split(data: &'a str) -> Sections<'a> {
+        let mut sections: Vec<Section<'a>> = vec![];
+        let mut current: Option<Section<'a>> = None;
+
+        for (i, line) in data.split('\n').enumerate() {
+            let line_number = i + 1;
+
+            if line.trim().is_empty() {
+                if let Some(section) = current.take() {
+                    sections.push(section);
+                }
+                continue;
+            }
+
+            let starts_section = match &current {
+                None => true,
+                Some(_) => is_header(line),
+            };
+            if starts_section {
+                if let Some(section) = current.take() {
+                    sections.push(section);
+                }
+
+                let header = if is_header(line) {
+                    Some(line.trim_end_matches(':'))
+                } else {
+                    None
+                };
+                current = Some(Section {
+                    index: sections.len(),
+                    header,
+                    first_line: if header.is_some() {
+                        line_number + 1
+                    } else {
+                        line_number
+                    },
+                    lines: if header.is_some() { vec![] } else { vec![line] },
+                });
+                continue;
+            }
+
+            if let Some(section) = current.as_mut() {
+                section.lines.push(line);
+            }
+        }
+
+        if let Some(section) = current {
+            sections.push(section);
+        }
+
+        Sections { sections }
+    }
+
+    pub fn expect_count(self, count: usize) -> Result<Sections<'a>, String> {
+        if self.sections.len() != count {
+            let names = self.sections.iter().map(Section::name).collect::<Vec<_>>();
+            return Err(format!(
+                "Expected {} sections of input, but found {} ({})",
+                count,
+                self.sections.len(),
+                names.join(", ")
+            ));
+        }
+
+        Ok(self)
+    }
+
+    pub fn at(&self, index: usize) -> Result<&Section<'a>, String> {
+        self.sections.get(index).ok_or_else(|| {
+            format!(
+                "Expected at least {} sections of input, but found {}",
+                index + 1,
+                self.sections.len()
+            )
+        })
+    }
+
+    pub fn named(&self, header: &str) -> Result<&Section<'a>, String> {
+        self.sections
+            .iter()
+            .find(|s| s.header == Some(header))
+            .ok_or_else(|| format!("Could not find a '{}:' section in the input", header))
+    }
+}
+
+#[test]
+fn test_split_sections() {
+    let data = "class: 1-3\nrow: 6-11\n\nyour ticket:\n7,1\n\nnearby tickets:\n7,3\n40,4";
+    let sections = Sections::split(data).expect_count(3).unwrap();
+
+    assert_eq!(
+        sections.at(0).unwrap().lines(),
+        &["class: 1-3", "row: 6-11"]
+    );
+    assert_eq!(sections.at(0).unwrap().name(), "#1");
+    assert_eq!(sections.named("your ticket").unwrap().lines(), &["7,1"]);
+    assert_eq!(sections.at(2).unwrap().lines(), &["7,3", "40,4"]);
+    assert!(sections.at(3).is_err());
+    assert!(sections.named("Player 1").is_err());
+
+    // Headers start a new section, even without a blank line before them.
+    let sections = Sections::split("Player 1:\n9\n2\nPlayer 2:\n5\n\n")
+        .expect_count(2)
+        .unwrap();
+    assert_eq!(sections.named("Player 1").unwrap().lines(), &["9", "2"]);
+    assert_eq!(sections.at(1).unwrap().name(), "'Player 2'");
+
+    assert_eq!(
+        Sections::split("a\n\nb").expect_count(3),
+        Err("Expected 3 sections of input, but found 2 (#1, #2)".to_owned())
+    );
+}
+
+#[test]
+fn test_section_errors() {
+    let data = "Player 1:\n9\n2\n\nPlayer 2:\n5\nx";
+    let sections = Sections::split(data);
+    let parse = |l: &str| l.parse::<u32>().map_err(|e| format!("{} ({})", e, l));
+
+    assert_eq!(
+        sections.named("Player 1").unwrap().parse_lines(parse),
+        Ok(vec![9, 2])
+    );
+    assert_eq!(
+        sections.named("Player 2").unwrap().parse_lines(parse),
+        Err("Section 'Player 2', line 7: invalid digit found in string (x)".to_owned())
+    );
+    assert_eq!(
+        sections.named("Player 1").unwrap().parse_single(parse),
+        Err("Section 'Player 1', line 2: Expected a single line, but got 2".to_owned())
+    );
+}
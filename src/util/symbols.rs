@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+// Puzzle inputs are full of names (bag colours, ticket fields, ingredients) that we only ever
+// compare. Interning them once gives us a small Copy id to hash and compare instead.
+
+#[derive(Eq, PartialEq, Hash, Ord, PartialOrd, Debug, Copy, Clone)]
+pub struct Symbol(u32);
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    ids: HashMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        self.ids.insert(name.to_owned(), symbol);
+        self.names.push(name.to_owned());
+        symbol
+    }
+
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+#[test]
+fn test_interner() {
+    let mut interner = Interner::new();
+    let gold = interner.intern("shiny gold");
+    let red = interner.intern("dark red");
+
+    assert_ne!(gold, red);
+    assert_eq!(interner.intern("shiny gold"), gold);
+    assert_eq!(interner.get("dark red"), Some(red));
+    assert_eq!(interner.get("faded blue"), None);
+    assert_eq!(interner.resolve(gold), "shiny gold");
+    assert_eq!(interner.resolve(red), "dark red");
+}
+
+// Benchmark comparing the same lookups in a HashSet of Strings and a HashSet of symbols, so the
+// difference is only what interning gains (hashing and comparing an id instead of a string). The
+// time spent interning the names is shown separately. Run with
+// `cargo test --release -- --ignored --nocapture bench_`.
+#[test]
+#[ignore]
+fn bench_symbol_lookup() {
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    let names = (0..600)
+        .map(|i| format!("colour {} shade {}", i % 37, i))
+        .collect::<Vec<_>>();
+    let rounds = 2000;
+
+    let strings = names
+        .iter()
+        .step_by(2)
+        .cloned()
+        .collect::<HashSet<String>>();
+    let start = Instant::now();
+    let mut string_hits = 0;
+    for _ in 0..rounds {
+        string_hits += names.iter().filter(|n| strings.contains(*n)).count();
+    }
+    let string_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut interner = Interner::new();
+    let lookup = names.iter().map(|n| interner.intern(n)).collect::<Vec<_>>();
+    let intern_time = start.elapsed();
+
+    let symbols = lookup.iter().step_by(2).copied().collect::<HashSet<_>>();
+    let start = Instant::now();
+    let mut symbol_hits = 0;
+    for _ in 0..rounds {
+        symbol_hits += lookup.iter().filter(|s| symbols.contains(s)).count();
+    }
+    let symbol_time = start.elapsed();
+
+    assert_eq!(string_hits, symbol_hits);
+    println!(
+        "Strings in HashSet: {:?}, symbols in HashSet: {:?} (plus {:?} interning)",
+        string_time, symbol_time, intern_time
+    );
+}